@@ -0,0 +1,269 @@
+use rayon::prelude::*;
+
+/// All the sequences of `length` symbols, with repetitions, in lexicographic
+/// order of the symbol positions (the last symbol changes fastest)
+#[derive(Debug, Clone)]
+pub struct CartesianPower<T> {
+    symbols: Vec<T>,
+    length: usize,
+    len: usize,
+}
+
+impl<T: Clone + Send + Sync> CartesianPower<T> {
+    /// Panics if there are more sequences than `usize` can count
+    pub fn new(symbols: &[T], length: usize) -> Self {
+        Self::try_new(symbols, length).expect("Too many sequences to count")
+    }
+
+    /// Returns `None` if there are more sequences than `usize` can count
+    pub fn try_new(symbols: &[T], length: usize) -> Option<Self> {
+        let len = u32::try_from(length)
+            .ok()
+            .and_then(|length| symbols.len().checked_pow(length))?;
+
+        Some(Self {
+            symbols: Vec::from(symbols),
+            length,
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The sequence at `index`, without generating the ones before it
+    pub fn get(&self, mut index: usize) -> Option<Vec<T>> {
+        if index >= self.len {
+            return None;
+        }
+
+        let mut sequence = Vec::with_capacity(self.length);
+        for _ in 0..self.length {
+            sequence.push(self.symbols[index % self.symbols.len()].clone());
+            index /= self.symbols.len();
+        }
+        sequence.reverse();
+
+        Some(sequence)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<T>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = Vec<T>> + '_ {
+        (0..self.len)
+            .into_par_iter()
+            .map(|index| self.get(index).unwrap())
+    }
+}
+
+/// All the ordered selections of `k` distinct items, in lexicographic order
+/// of the item positions
+#[derive(Debug, Clone)]
+pub struct KPermutations<T> {
+    items: Vec<T>,
+    k: usize,
+    len: usize,
+}
+
+impl<T: Clone + Send + Sync> KPermutations<T> {
+    /// Panics if there are more permutations than `usize` can count
+    pub fn new(items: &[T], k: usize) -> Self {
+        let len = falling_factorial(items.len(), k).expect("Too many permutations to count");
+
+        Self {
+            items: Vec::from(items),
+            k,
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The permutation at `index`, without generating the ones before it
+    pub fn get(&self, mut index: usize) -> Option<Vec<T>> {
+        if index >= self.len {
+            return None;
+        }
+
+        let mut pool: Vec<usize> = (0..self.items.len()).collect();
+        let mut permutation = Vec::with_capacity(self.k);
+        for position in 0..self.k {
+            // Each choice at this position is followed by this many permutations
+            let block = falling_factorial(pool.len() - 1, self.k - position - 1).unwrap();
+            let choice = pool.remove(index / block);
+            index %= block;
+            permutation.push(self.items[choice].clone());
+        }
+
+        Some(permutation)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<T>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = Vec<T>> + '_ {
+        (0..self.len)
+            .into_par_iter()
+            .map(|index| self.get(index).unwrap())
+    }
+}
+
+/// All the unordered selections of `k` distinct items, in lexicographic
+/// order of the item positions
+#[derive(Debug, Clone)]
+pub struct Combinations<T> {
+    items: Vec<T>,
+    k: usize,
+    len: usize,
+}
+
+impl<T: Clone + Send + Sync> Combinations<T> {
+    /// Panics if there are more combinations than `usize` can count
+    pub fn new(items: &[T], k: usize) -> Self {
+        let len = binomial(items.len(), k).expect("Too many combinations to count");
+
+        Self {
+            items: Vec::from(items),
+            k,
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The combination at `index`, without generating the ones before it
+    pub fn get(&self, mut index: usize) -> Option<Vec<T>> {
+        if index >= self.len {
+            return None;
+        }
+
+        let n = self.items.len();
+        let mut combination = Vec::with_capacity(self.k);
+        let mut candidate = 0;
+        for position in 0..self.k {
+            loop {
+                // Combinations that pick `candidate` at this position
+                let block = binomial(n - candidate - 1, self.k - position - 1).unwrap();
+                if index < block {
+                    break;
+                }
+                index -= block;
+                candidate += 1;
+            }
+            combination.push(self.items[candidate].clone());
+            candidate += 1;
+        }
+
+        Some(combination)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<T>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = Vec<T>> + '_ {
+        (0..self.len)
+            .into_par_iter()
+            .map(|index| self.get(index).unwrap())
+    }
+}
+
+/// All the subsets of some items, where the subset at index `i` holds the
+/// items whose bit is set in `i`
+#[derive(Debug, Clone)]
+pub struct Subsets<T> {
+    items: Vec<T>,
+    len: usize,
+}
+
+impl<T: Clone + Send + Sync> Subsets<T> {
+    /// Panics if there are more subsets than `usize` can count
+    pub fn new(items: &[T]) -> Self {
+        let len = u32::try_from(items.len())
+            .ok()
+            .and_then(|n| 1usize.checked_shl(n))
+            .filter(|len| *len != 0)
+            .expect("Too many subsets to count");
+
+        Self {
+            items: Vec::from(items),
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The subset at `index`, without generating the ones before it
+    pub fn get(&self, index: usize) -> Option<Vec<T>> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(
+            self.items
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| index & (1 << bit) != 0)
+                .map(|(_, item)| item.clone())
+                .collect(),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<T>> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = Vec<T>> + '_ {
+        (0..self.len)
+            .into_par_iter()
+            .map(|index| self.get(index).unwrap())
+    }
+}
+
+/// `n! / (n - k)!`, or zero when `k > n`
+fn falling_factorial(n: usize, k: usize) -> Option<usize> {
+    if k > n {
+        return Some(0);
+    }
+
+    ((n - k + 1)..=n).try_fold(1usize, |acc, factor| acc.checked_mul(factor))
+}
+
+/// `n` choose `k`, or zero when `k > n`
+fn binomial(n: usize, k: usize) -> Option<usize> {
+    if k > n {
+        return Some(0);
+    }
+
+    let k = k.min(n - k);
+    (0..k).try_fold(1usize, |acc, i| {
+        // Always divisible, as acc * (n - i) is a multiple of (i + 1)!
+        acc.checked_mul(n - i).map(|product| product / (i + 1))
+    })
+}
@@ -1,3 +1,4 @@
+pub mod combinatorics;
 pub mod matrix;
 
 use std::{fs, path::Path};
@@ -192,15 +192,15 @@ impl<T: Scalar> TraversableMatrix<T> {
         self.inner.column_iter()
     }
 
-    pub fn left_diagonal_iter(&self) -> DiagonalIter<T> {
+    pub fn left_diagonal_iter(&self) -> DiagonalIter<'_, T> {
         DiagonalIter::new(self, DiagonalDirection::Left)
     }
 
-    pub fn right_diagonal_iter(&self) -> DiagonalIter<T> {
+    pub fn right_diagonal_iter(&self) -> DiagonalIter<'_, T> {
         DiagonalIter::new(self, DiagonalDirection::Right)
     }
 
-    pub fn diagonal_iter(&self) -> DiagonalIter<T> {
+    pub fn diagonal_iter(&self) -> DiagonalIter<'_, T> {
         DiagonalIter::new(self, DiagonalDirection::Both)
     }
 
@@ -0,0 +1,289 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::program::{Instruction, Operand, Register};
+
+/// Operands of `mul` have at most this many digits
+const MAX_OPERAND_DIGITS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Instruction(Instruction),
+    /// Something that looks like an instruction but is not quite one
+    NearMiss(Defect),
+}
+
+/// What an instruction takes between its parentheses
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Parameter {
+    /// A number, or a register in extended programs
+    Value,
+    Register,
+}
+
+/// The instructions of the puzzle. `don't` has to be tried before `do`,
+/// which it starts with.
+const INSTRUCTIONS: [(&str, &[Parameter]); 3] = [
+    ("mul", &[Parameter::Value, Parameter::Value]),
+    ("don't", &[]),
+    ("do", &[]),
+];
+
+/// The instructions only extended programs have
+const EXTENDED_INSTRUCTIONS: [(&str, &[Parameter]); 7] = [
+    ("add", &[Parameter::Value]),
+    ("sub", &[Parameter::Value]),
+    ("push", &[Parameter::Value]),
+    ("pop", &[Parameter::Register]),
+    ("store", &[Parameter::Register]),
+    ("ifz", &[]),
+    ("ifnz", &[]),
+];
+
+/// What keeps a near miss from being an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Defect {
+    /// Instructions cannot have spaces in them, e.g. `mul ( 2 , 3 )`
+    Whitespace,
+    /// An operand with too many digits, e.g. `mul(1234,5)`
+    LongOperand,
+    /// A character that has no place there, e.g. the `*` of `mul(4*`
+    Unexpected(char),
+    /// The memory ends in the middle of the instruction
+    UnexpectedEnd,
+    /// A register that does not exist, e.g. `r7`
+    UnknownRegister,
+    /// A number where only a register can go, e.g. `pop(3)`
+    ExpectedRegister,
+}
+
+impl Display for Defect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Defect::Whitespace => write!(f, "whitespace inside the instruction"),
+            Defect::LongOperand => {
+                write!(f, "operand with more than {} digits", MAX_OPERAND_DIGITS)
+            }
+            Defect::Unexpected(c) => write!(f, "unexpected '{}'", c.escape_debug()),
+            Defect::UnexpectedEnd => write!(f, "unexpected end of memory"),
+            Defect::UnknownRegister => write!(
+                f,
+                "unknown register, expected r0 to r{}",
+                Register::COUNT - 1
+            ),
+            Defect::ExpectedRegister => write!(f, "expected a register"),
+        }
+    }
+}
+
+/// A token with the byte range of the memory it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Reads instructions out of corrupted memory, one token per instruction or
+/// near miss. Everything else is skipped.
+///
+/// A near miss is a keyword followed by `(` that does not make a valid
+/// instruction. When it breaks off, lexing resumes where it did, so that an
+/// instruction right after the break is still found.
+pub struct Lexer<'a> {
+    code: &'a str,
+    position: usize,
+    extended: bool,
+}
+
+impl<'a> Lexer<'a> {
+    /// A lexer for the puzzle's `mul`, `do` and `don't` instructions
+    pub fn new(code: &'a str) -> Self {
+        Self {
+            code,
+            position: 0,
+            extended: false,
+        }
+    }
+
+    /// A lexer that also reads the instructions of the VM and register
+    /// operands
+    pub fn extended(code: &'a str) -> Self {
+        Self {
+            extended: true,
+            ..Self::new(code)
+        }
+    }
+
+    /// Reads the instruction starting at the current position, if there is
+    /// one, returning its kind and where it ends
+    fn instruction(&self) -> Option<(TokenKind, usize)> {
+        let rest = &self.code[self.position..];
+        let extended: &[(&str, &[Parameter])] = if self.extended {
+            &EXTENDED_INSTRUCTIONS
+        } else {
+            &[]
+        };
+        let (keyword, parameters) = INSTRUCTIONS
+            .iter()
+            .chain(extended)
+            .find(|(keyword, _)| rest.starts_with(keyword))?;
+
+        let mut cursor = Cursor {
+            code: self.code,
+            position: self.position + keyword.len(),
+            defect: None,
+        };
+        cursor.skip_whitespace();
+        if !cursor.eat('(') {
+            // Nothing like an instruction, just the same letters
+            return None;
+        }
+
+        let mut operands = Vec::with_capacity(parameters.len());
+        for (idx, parameter) in parameters.iter().enumerate() {
+            if idx > 0 {
+                cursor.skip_whitespace();
+                if let Err(defect) = cursor.expect(',') {
+                    return Some((TokenKind::NearMiss(defect), cursor.position));
+                }
+            }
+            cursor.skip_whitespace();
+            match cursor.operand(*parameter, self.extended) {
+                Ok(operand) => operands.push(operand),
+                Err(defect) => return Some((TokenKind::NearMiss(defect), cursor.position)),
+            }
+        }
+        cursor.skip_whitespace();
+        if let Err(defect) = cursor.expect(')') {
+            return Some((TokenKind::NearMiss(defect), cursor.position));
+        }
+
+        if let Some(defect) = cursor.defect {
+            return Some((TokenKind::NearMiss(defect), cursor.position));
+        }
+        let register = |idx: usize| match operands[idx] {
+            Operand::Register(register) => register,
+            Operand::Number(_) => unreachable!("Register parameters only take registers"),
+        };
+        let instruction = match *keyword {
+            "mul" => Instruction::Mul(operands[0], operands[1]),
+            "don't" => Instruction::Disable,
+            "do" => Instruction::Enable,
+            "add" => Instruction::Add(operands[0]),
+            "sub" => Instruction::Sub(operands[0]),
+            "push" => Instruction::Push(operands[0]),
+            "pop" => Instruction::Pop(register(0)),
+            "store" => Instruction::Store(register(0)),
+            "ifz" => Instruction::EnableIfZero,
+            "ifnz" => Instruction::EnableIfNotZero,
+            other => unreachable!("No instruction for keyword {}", other),
+        };
+
+        Some((TokenKind::Instruction(instruction), cursor.position))
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.code.len() {
+            if let Some((kind, end)) = self.instruction() {
+                let span = self.position..end;
+                self.position = end;
+                return Some(Token { kind, span });
+            }
+
+            self.position += self.code[self.position..]
+                .chars()
+                .next()
+                .map_or(1, char::len_utf8);
+        }
+
+        None
+    }
+}
+
+/// Walks through an instruction, remembering the first defect that does not
+/// stop it from being read to the end
+struct Cursor<'a> {
+    code: &'a str,
+    position: usize,
+    defect: Option<Defect>,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.code[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Defect> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn unexpected(&self) -> Defect {
+        self.peek()
+            .map_or(Defect::UnexpectedEnd, Defect::Unexpected)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.position += c.len_utf8();
+            self.defect.get_or_insert(Defect::Whitespace);
+        }
+    }
+
+    /// Reads an operand, registers being only allowed in extended programs.
+    /// Defects that do not stop the operand from being read are remembered
+    /// and a placeholder is returned.
+    fn operand(&mut self, parameter: Parameter, extended: bool) -> Result<Operand, Defect> {
+        if extended && self.eat('r') {
+            let index = self.number()?;
+            return match Register::new(index as usize) {
+                Some(register) => Ok(Operand::Register(register)),
+                None => {
+                    self.defect.get_or_insert(Defect::UnknownRegister);
+                    Ok(Operand::Register(Register::new(0).unwrap()))
+                }
+            };
+        }
+
+        let number = self.number()?;
+        if parameter == Parameter::Register {
+            self.defect.get_or_insert(Defect::ExpectedRegister);
+        }
+
+        Ok(Operand::Number(number))
+    }
+
+    fn number(&mut self) -> Result<i32, Defect> {
+        let digits = self.code[self.position..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            return Err(self.unexpected());
+        }
+
+        let number = &self.code[self.position..self.position + digits];
+        self.position += digits;
+        if digits > MAX_OPERAND_DIGITS {
+            self.defect.get_or_insert(Defect::LongOperand);
+            return Ok(0);
+        }
+
+        Ok(number.parse().expect("Digits make a number"))
+    }
+}
@@ -1,16 +1,89 @@
+mod lexer;
 mod program;
+mod vm;
 
-use std::{env, fs, path::Path};
+use std::{env, fs, path::Path, process};
 
+use lexer::{Lexer, TokenKind};
 use program::Program;
 
+const ACCEPTED_COLOR: &str = "\x1b[32m";
+const NEAR_MISS_COLOR: &str = "\x1b[31m";
+const RESET_COLOR: &str = "\x1b[0m";
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let rc: i32 = Program::new(read_input(
-        args.get(1).unwrap_or(&String::from("input.txt")),
-    ))
-    .execute();
-    println!("Program output: {}", rc);
+    let code = read_input(args.get(1).unwrap_or(&String::from("input.txt")));
+
+    let extended = args.iter().any(|arg| arg == "--vm");
+    let lexer = || {
+        if extended {
+            Lexer::extended(&code)
+        } else {
+            Lexer::new(&code)
+        }
+    };
+
+    if args.iter().any(|arg| arg == "--tokens") {
+        print_tokens(&code, lexer());
+    }
+    if args.iter().any(|arg| arg == "--highlight") {
+        print_highlighted(&code, lexer());
+    }
+
+    if extended {
+        match Program::extended(&code).run() {
+            Ok(vm) => println!("Final VM state: {}", vm),
+            Err(err) => {
+                eprintln!("Program failed: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        match Program::new(&code).execute() {
+            Ok(rc) => println!("Program output: {}", rc),
+            Err(err) => {
+                eprintln!("Program failed: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Prints every instruction and near miss with its byte range
+fn print_tokens(code: &str, lexer: Lexer) {
+    for token in lexer {
+        let text = &code[token.span.clone()];
+        match token.kind {
+            TokenKind::NearMiss(defect) => println!(
+                "{}..{}: near miss {:?}, {}",
+                token.span.start, token.span.end, text, defect
+            ),
+            TokenKind::Instruction(_) => {
+                println!("{}..{}: {}", token.span.start, token.span.end, text)
+            }
+        }
+    }
+}
+
+/// Prints the memory with instructions in green and near misses in red
+fn print_highlighted(code: &str, lexer: Lexer) {
+    let mut position = 0;
+    for token in lexer {
+        let color = match token.kind {
+            TokenKind::NearMiss(_) => NEAR_MISS_COLOR,
+            TokenKind::Instruction(_) => ACCEPTED_COLOR,
+        };
+        print!(
+            "{}{}{}{}",
+            &code[position..token.span.start],
+            color,
+            &code[token.span.clone()],
+            RESET_COLOR
+        );
+        position = token.span.end;
+    }
+    println!("{}", &code[position..]);
 }
 
 fn read_input<P: AsRef<Path>>(file: P) -> String {
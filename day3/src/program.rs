@@ -1,60 +1,101 @@
-static INSTRUCTION_PARSER_RE: &str = r#"mul\(([0-9]{1,3}),([0-9]{1,3})\)|do(?:n\'t)?\(\)"#;
+use std::ops::Range;
 
+use crate::{
+    lexer::{Lexer, TokenKind},
+    vm::{Vm, VmError},
+};
+
+/// One of the VM registers, `r0` to `r3`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Register(usize);
+
+impl Register {
+    pub const COUNT: usize = 4;
+
+    pub fn new(index: usize) -> Option<Self> {
+        (index < Self::COUNT).then_some(Self(index))
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Where an instruction reads a value from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Number(i32),
+    Register(Register),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
-    Mul(i32, i32),
+    Mul(Operand, Operand),
     Enable,
     Disable,
+    /// Adds to the accumulator
+    Add(Operand),
+    /// Subtracts from the accumulator
+    Sub(Operand),
+    Push(Operand),
+    /// Pops the top of the stack into a register
+    Pop(Register),
+    /// Copies the accumulator into a register
+    Store(Register),
+    /// Enables math if the accumulator is zero, disables it otherwise
+    EnableIfZero,
+    /// Enables math unless the accumulator is zero
+    EnableIfNotZero,
 }
 
 pub struct Program {
     instructions: Vec<Instruction>,
+    /// Where each instruction was read from in the memory dump
+    spans: Vec<Range<usize>>,
 }
 
 impl Program {
+    /// A program of the puzzle's `mul`, `do` and `don't` instructions
     pub fn new<S: AsRef<str>>(code: S) -> Self {
-        let regex = regex::Regex::new(INSTRUCTION_PARSER_RE).unwrap();
+        Self::read(Lexer::new(code.as_ref()))
+    }
+
+    /// A program that can also use the registers, stack and conditional
+    /// instructions of the VM
+    pub fn extended<S: AsRef<str>>(code: S) -> Self {
+        Self::read(Lexer::extended(code.as_ref()))
+    }
+
+    fn read(lexer: Lexer) -> Self {
         let mut instructions = vec![];
-        for capture_match in regex.captures_iter(code.as_ref()) {
-            let matched = capture_match.get(0).unwrap().as_str();
-            if matched == "do()" {
-                instructions.push(Instruction::Enable);
-            } else if matched == "don't()" {
-                instructions.push(Instruction::Disable);
-            } else {
-                let operand1: i32 = capture_match
-                    .get(1)
-                    .expect("Syntax error: expected operand")
-                    .as_str()
-                    .parse()
-                    .expect("Syntax error: operand must be a number");
-                let operand2: i32 = capture_match
-                    .get(2)
-                    .expect("Syntax error: expected operand")
-                    .as_str()
-                    .parse()
-                    .expect("Syntax error: operand must be a number");
-                instructions.push(Instruction::Mul(operand1, operand2));
+        let mut spans = vec![];
+        for token in lexer {
+            if let TokenKind::Instruction(instruction) = token.kind {
+                instructions.push(instruction);
+                spans.push(token.span);
             }
         }
 
-        Self { instructions }
+        Self {
+            instructions,
+            spans,
+        }
     }
 
-    pub fn execute(&self) -> i32 {
-        let mut rc = 0;
-        let mut enable_math = true;
-        for instruction in self.instructions.iter() {
-            match instruction {
-                Instruction::Mul(op1, op2) => {
-                    if enable_math {
-                        rc += op1 * op2;
-                    }
-                }
-                Instruction::Enable => enable_math = true,
-                Instruction::Disable => enable_math = false,
-            };
+    /// Runs the program on a fresh VM, returning its final state
+    pub fn run(&self) -> Result<Vm, VmError> {
+        let mut vm = Vm::default();
+        for (instruction, span) in self.instructions.iter().zip(self.spans.iter()) {
+            vm.step(instruction).map_err(|kind| VmError {
+                span: span.clone(),
+                kind,
+            })?;
         }
 
-        rc
+        Ok(vm)
+    }
+
+    pub fn execute(&self) -> Result<i32, VmError> {
+        self.run().map(|vm| vm.accumulator)
     }
 }
@@ -0,0 +1,111 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::program::{Instruction, Operand, Register};
+
+/// The state of the machine programs run on. Math instructions only run
+/// while math is enabled, everything but `do()`, `don't()`, `ifz()` and
+/// `ifnz()` counting as math.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vm {
+    pub accumulator: i32,
+    pub registers: [i32; Register::COUNT],
+    pub stack: Vec<i32>,
+    pub enabled: bool,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self {
+            accumulator: 0,
+            registers: [0; Register::COUNT],
+            stack: vec![],
+            enabled: true,
+        }
+    }
+}
+
+impl Display for Vm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "accumulator {}, registers {:?}, stack {:?}, math {}",
+            self.accumulator,
+            self.registers,
+            self.stack,
+            if self.enabled { "enabled" } else { "disabled" }
+        )
+    }
+}
+
+impl Vm {
+    pub fn step(&mut self, instruction: &Instruction) -> Result<(), VmErrorKind> {
+        match instruction {
+            Instruction::Enable => self.enabled = true,
+            Instruction::Disable => self.enabled = false,
+            Instruction::EnableIfZero => self.enabled = self.accumulator == 0,
+            Instruction::EnableIfNotZero => self.enabled = self.accumulator != 0,
+            _ if !self.enabled => (),
+            Instruction::Mul(op1, op2) => {
+                let product = self
+                    .value(op1)
+                    .checked_mul(self.value(op2))
+                    .ok_or(VmErrorKind::Overflow)?;
+                self.accumulate(product)?;
+            }
+            Instruction::Add(operand) => self.accumulate(self.value(operand))?,
+            Instruction::Sub(operand) => {
+                self.accumulator = self
+                    .accumulator
+                    .checked_sub(self.value(operand))
+                    .ok_or(VmErrorKind::Overflow)?;
+            }
+            Instruction::Push(operand) => self.stack.push(self.value(operand)),
+            Instruction::Pop(register) => {
+                self.registers[register.index()] =
+                    self.stack.pop().ok_or(VmErrorKind::StackUnderflow)?;
+            }
+            Instruction::Store(register) => self.registers[register.index()] = self.accumulator,
+        }
+
+        Ok(())
+    }
+
+    fn value(&self, operand: &Operand) -> i32 {
+        match operand {
+            Operand::Number(n) => *n,
+            Operand::Register(register) => self.registers[register.index()],
+        }
+    }
+
+    fn accumulate(&mut self, value: i32) -> Result<(), VmErrorKind> {
+        self.accumulator = self
+            .accumulator
+            .checked_add(value)
+            .ok_or(VmErrorKind::Overflow)?;
+        Ok(())
+    }
+}
+
+/// An instruction that could not run, with the byte range it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub span: Range<usize>,
+    pub kind: VmErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmErrorKind {
+    StackUnderflow,
+    /// A value does not fit in 32 bits
+    Overflow,
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction at {}..{}: ", self.span.start, self.span.end)?;
+        match self.kind {
+            VmErrorKind::StackUnderflow => write!(f, "pop from an empty stack"),
+            VmErrorKind::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::graph::PageGraph;
+
+/// For each page, every page the rules force after it and before it,
+/// directly or through other pages
+#[derive(Debug, Clone, Default)]
+pub struct Closure {
+    descendants: HashMap<i32, HashSet<i32>>,
+    ancestors: HashMap<i32, HashSet<i32>>,
+}
+
+impl Closure {
+    pub fn new(graph: &PageGraph) -> Self {
+        let mut closure = Self::default();
+        for page in graph.pages() {
+            for after in graph.descendants(page) {
+                closure.insert(page, after);
+            }
+        }
+
+        closure
+    }
+
+    /// Whether the rules force `before` to come before `after`
+    pub fn precedes(&self, before: i32, after: i32) -> bool {
+        self.descendants
+            .get(&before)
+            .is_some_and(|descendants| descendants.contains(&after))
+    }
+
+    pub fn descendants(&self, page: i32) -> HashSet<i32> {
+        self.descendants.get(&page).cloned().unwrap_or_default()
+    }
+
+    pub fn ancestors(&self, page: i32) -> HashSet<i32> {
+        self.ancestors.get(&page).cloned().unwrap_or_default()
+    }
+
+    /// Accounts for a new `before` to `after` edge of the graph
+    pub fn add_edge(&mut self, before: i32, after: i32) {
+        let mut sources = self.ancestors(before);
+        sources.insert(before);
+        let mut targets = self.descendants(after);
+        targets.insert(after);
+
+        for source in sources.iter() {
+            for target in targets.iter() {
+                self.insert(*source, *target);
+            }
+        }
+    }
+
+    /// Accounts for the removal of an edge leaving `before` from `graph`,
+    /// which only changes what `before` and its ancestors reach
+    pub fn remove_edge(&mut self, graph: &PageGraph, before: i32) {
+        let mut sources = self.ancestors(before);
+        sources.insert(before);
+
+        for source in sources {
+            let reachable = graph.descendants(source);
+            for lost in self.descendants(source).difference(&reachable) {
+                if let Some(ancestors) = self.ancestors.get_mut(lost) {
+                    ancestors.remove(&source);
+                }
+            }
+            self.descendants.insert(source, reachable);
+        }
+    }
+
+    fn insert(&mut self, before: i32, after: i32) {
+        self.descendants.entry(before).or_default().insert(after);
+        self.ancestors.entry(after).or_default().insert(before);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashSet, fmt::Display};
+
+use crate::{
+    orderings::MAX_COUNTED_PAGES,
+    safety_manual::{OrderingError, PagesForUpdate, SafetyManualUpdates},
+};
+
+/// What a minimal correction keeps as small as possible
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CorrectionGoal {
+    /// The number of pages taken out and put back elsewhere
+    FewestMoves,
+    /// The number of pairs of pages that end up in the opposite order, i.e.
+    /// the Kendall tau distance to the original order
+    FewestSwaps,
+}
+
+/// A page taken out of an update and put back elsewhere, with its (zero
+/// based) positions in the original and the corrected update
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    pub page: i32,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move page {} from position {} to position {}",
+            self.page,
+            self.from + 1,
+            self.to + 1
+        )
+    }
+}
+
+/// A valid order for an update, with the edits that produce it from the
+/// original order
+#[derive(Debug, Clone)]
+pub struct Correction {
+    pub pages: PagesForUpdate,
+    /// The pages that moved, every other page keeps its relative order
+    pub moves: Vec<Move>,
+    /// How many pairs of pages ended up in the opposite order
+    pub swaps: usize,
+}
+
+impl Correction {
+    pub fn new(
+        safety_manual: &SafetyManualUpdates,
+        pages: &PagesForUpdate,
+        goal: CorrectionGoal,
+    ) -> Result<Self, OrderingError> {
+        // Contradictory rules leave nothing to minimize
+        safety_manual.correct(pages)?;
+
+        let order = match goal {
+            CorrectionGoal::FewestMoves => fewest_moves(safety_manual, pages)?,
+            CorrectionGoal::FewestSwaps => fewest_swaps(safety_manual, pages)?,
+        };
+
+        Ok(Self::from_order(pages, &order))
+    }
+
+    /// Describes the update reordered so that position `i` holds the page
+    /// originally at `order[i]`
+    fn from_order(pages: &PagesForUpdate, order: &[usize]) -> Self {
+        let kept = longest_increasing(order);
+        let moves = order
+            .iter()
+            .enumerate()
+            .filter(|(_, from)| !kept.contains(from))
+            .map(|(to, from)| Move {
+                page: pages[*from],
+                from: *from,
+                to,
+            })
+            .collect();
+
+        let swaps = order
+            .iter()
+            .enumerate()
+            .map(|(idx, from)| {
+                order[idx + 1..]
+                    .iter()
+                    .filter(|other| *other < from)
+                    .count()
+            })
+            .sum();
+
+        Self {
+            pages: order.iter().map(|from| pages[*from]).collect(),
+            moves,
+            swaps,
+        }
+    }
+}
+
+/// A valid order that keeps as many pages as possible where they are,
+/// relative to each other.
+///
+/// Two pages can both stay unless the rules, possibly through other pages of
+/// the update, require the later one to come first. Such conflicts form a
+/// partial order on the positions, so the largest set of pages that can stay
+/// is its largest antichain, found from a maximum matching (Dilworth and
+/// König).
+fn fewest_moves(
+    safety_manual: &SafetyManualUpdates,
+    pages: &PagesForUpdate,
+) -> Result<Vec<usize>, OrderingError> {
+    let n = pages.len();
+    let subgraph = safety_manual.graph().subgraph(pages);
+    let followers: Vec<HashSet<i32>> = pages
+        .iter()
+        .map(|page| subgraph.descendants(*page))
+        .collect();
+    // conflicts[i] holds the later positions whose page must come before the
+    // page at position i
+    let conflicts: Vec<Vec<usize>> = (0..n)
+        .map(|i| {
+            ((i + 1)..n)
+                .filter(|j| followers[*j].contains(&pages[i]))
+                .collect()
+        })
+        .collect();
+
+    let mut matched: Vec<Option<usize>> = vec![None; n];
+    for i in 0..n {
+        augment(i, &conflicts, &mut vec![false; n], &mut matched);
+    }
+
+    // Alternating paths from the unmatched positions find a minimum vertex
+    // cover, the positions outside of it on both sides can all stay
+    let mut reached_left = vec![false; n];
+    let mut reached_right = vec![false; n];
+    let mut pending: Vec<usize> = (0..n).filter(|i| !matched.contains(&Some(*i))).collect();
+    while let Some(i) = pending.pop() {
+        if reached_left[i] {
+            continue;
+        }
+        reached_left[i] = true;
+        for j in conflicts[i].iter() {
+            if !reached_right[*j] {
+                reached_right[*j] = true;
+                pending.extend(matched[*j]);
+            }
+        }
+    }
+    let kept: Vec<usize> = (0..n)
+        .filter(|i| reached_left[*i] && !reached_right[*i])
+        .collect();
+
+    // Order the pages as the rules require, keeping the pages that stay in
+    // their original order and everything else as early as possible
+    let mut predecessors: Vec<Vec<usize>> = pages
+        .iter()
+        .map(|page| {
+            (0..n)
+                .filter(|j| safety_manual.has_rule(pages[*j], *page))
+                .collect()
+        })
+        .collect();
+    for pair in kept.windows(2) {
+        predecessors[pair[1]].push(pair[0]);
+    }
+
+    let mut placed = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    while order.len() < n {
+        let Some(next) =
+            (0..n).find(|i| !placed[*i] && predecessors[*i].iter().all(|j| placed[*j]))
+        else {
+            // Pages that can stay never conflict with the rules, so only
+            // contradictory rules leave pages that cannot be placed
+            let remaining = (0..n).filter(|i| !placed[*i]).map(|i| pages[i]).collect();
+            return Err(OrderingError::Cycle(
+                safety_manual.update_cycle(pages).unwrap_or(remaining),
+            ));
+        };
+        placed[next] = true;
+        order.push(next);
+    }
+
+    Ok(order)
+}
+
+/// Kuhn's augmenting path step for the matching of positions to the later
+/// positions they conflict with
+fn augment(
+    i: usize,
+    conflicts: &[Vec<usize>],
+    visited: &mut [bool],
+    matched: &mut [Option<usize>],
+) -> bool {
+    for j in conflicts[i].iter() {
+        if visited[*j] {
+            continue;
+        }
+        visited[*j] = true;
+        if matched[*j].is_none_or(|other| augment(other, conflicts, visited, matched)) {
+            matched[*j] = Some(i);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// A valid order with as few pairs of pages in the opposite order as
+/// possible, found by building the best order for each set of pages that can
+/// come first
+fn fewest_swaps(
+    safety_manual: &SafetyManualUpdates,
+    pages: &PagesForUpdate,
+) -> Result<Vec<usize>, OrderingError> {
+    let n = pages.len();
+    if n > MAX_COUNTED_PAGES {
+        return Err(OrderingError::TooManyPages {
+            pages: n,
+            limit: MAX_COUNTED_PAGES,
+        });
+    }
+
+    let required: Vec<usize> = pages
+        .iter()
+        .map(|page| {
+            (0..n)
+                .filter(|j| safety_manual.has_rule(pages[*j], *page))
+                .fold(0, |mask, j| mask | (1 << j))
+        })
+        .collect();
+
+    // For each set of pages, the fewest swaps among them when they come
+    // first, and the page that comes last to achieve it
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; 1 << n];
+    best[0] = Some((0, 0));
+    for prefix in 0..best.len() {
+        let Some((swaps, _)) = best[prefix] else {
+            continue;
+        };
+        for (k, before) in required.iter().enumerate() {
+            if prefix & (1 << k) != 0 || prefix & before != *before {
+                continue;
+            }
+            // Pages originally before k that are still to come end up after it
+            let crossed = (!prefix & ((1 << k) - 1)).count_ones() as usize;
+            let next = prefix | (1 << k);
+            if best[next].is_none_or(|(other, _)| swaps + crossed < other) {
+                best[next] = Some((swaps + crossed, k));
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(n);
+    let mut prefix = best.len() - 1;
+    while prefix != 0 {
+        let (_, last) = best[prefix].expect("Pages without a cycle can always be ordered");
+        order.push(last);
+        prefix &= !(1 << last);
+    }
+    order.reverse();
+
+    Ok(order)
+}
+
+/// The values of a longest strictly increasing subsequence
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // For each index, the length of the longest subsequence ending there and
+    // the index before it
+    let mut ending: Vec<(usize, Option<usize>)> = Vec::with_capacity(values.len());
+    for (idx, value) in values.iter().enumerate() {
+        let best = (0..idx)
+            .filter(|other| values[*other] < *value)
+            .max_by_key(|other| ending[*other].0);
+        ending.push(match best {
+            Some(other) => (ending[other].0 + 1, Some(other)),
+            None => (1, None),
+        });
+    }
+
+    let mut subsequence = HashSet::new();
+    let mut current = (0..values.len()).max_by_key(|idx| ending[*idx].0);
+    while let Some(idx) = current {
+        subsequence.insert(values[idx]);
+        current = ending[idx].1;
+    }
+
+    subsequence
+}
@@ -0,0 +1,84 @@
+use std::fmt::Write;
+
+use crate::{
+    graph::PageGraph,
+    safety_manual::{PagesForUpdate, SafetyManualUpdates},
+};
+
+/// Renders the whole rule graph in Graphviz DOT format. With `reduce`, the
+/// rules implied by other rules are left out, as long as the rules have no
+/// cycle.
+pub fn rules_to_dot(safety_manual: &SafetyManualUpdates, reduce: bool) -> String {
+    let graph = reduced(safety_manual.graph(), reduce);
+
+    let mut dot = String::from("digraph rules {\n");
+    for page in graph.pages() {
+        writeln!(dot, "  {};", page).unwrap();
+    }
+    for (before, after) in graph.edges() {
+        writeln!(dot, "  {} -> {};", before, after).unwrap();
+    }
+    dot.push_str("}\n");
+
+    dot
+}
+
+/// Renders the rules between the pages of an update in Graphviz DOT format.
+/// Rules the update breaks are drawn in red, even when `reduce` would leave
+/// them out, and the corrected order is drawn as a dashed blue path.
+pub fn update_to_dot(
+    safety_manual: &SafetyManualUpdates,
+    pages: &PagesForUpdate,
+    reduce: bool,
+) -> String {
+    let graph = reduced(&safety_manual.graph().subgraph(pages), reduce);
+    let violated: Vec<(i32, i32)> = safety_manual
+        .validate(pages)
+        .iter()
+        .map(|violation| (violation.rule.before(), violation.rule.after()))
+        .collect();
+
+    let mut dot = String::from("digraph update {\n");
+    writeln!(dot, "  label=\"{}\";", pages).unwrap();
+    for (position, page) in pages.iter().enumerate() {
+        writeln!(dot, "  {} [label=\"{} (#{})\"];", page, page, position + 1).unwrap();
+    }
+    for (before, after) in graph.edges() {
+        if !violated.contains(&(before, after)) {
+            writeln!(dot, "  {} -> {};", before, after).unwrap();
+        }
+    }
+    for (before, after) in violated.iter() {
+        writeln!(dot, "  {} -> {} [color=red, penwidth=2];", before, after).unwrap();
+    }
+
+    match safety_manual.correct(pages) {
+        Ok(corrected) => {
+            for (step, pair) in corrected.windows(2).enumerate() {
+                writeln!(
+                    dot,
+                    "  {} -> {} [color=blue, style=dashed, constraint=false, label=\"{}\"];",
+                    pair[0],
+                    pair[1],
+                    step + 1
+                )
+                .unwrap();
+            }
+        }
+        Err(err) => writeln!(dot, "  // no corrected order: {}", err).unwrap(),
+    }
+    dot.push_str("}\n");
+
+    dot
+}
+
+fn reduced(graph: &PageGraph, reduce: bool) -> PageGraph {
+    if reduce {
+        if let Some(reduction) = graph.transitive_reduction() {
+            return reduction;
+        }
+        eprintln!("The rules have a cycle, drawing them without reduction");
+    }
+
+    graph.clone()
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::safety_manual::OrderingRule;
+
+/// The pages that must follow each page, according to some ordering rules
+#[derive(Debug, Clone, Default)]
+pub struct PageGraph {
+    successors: BTreeMap<i32, Vec<i32>>,
+}
+
+impl PageGraph {
+    pub fn new<'a, I: IntoIterator<Item = &'a OrderingRule>>(rules: I) -> Self {
+        let mut successors: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for rule in rules {
+            successors.entry(rule.after()).or_default();
+            let after = successors.entry(rule.before()).or_default();
+            if !after.contains(&rule.after()) {
+                after.push(rule.after());
+            }
+        }
+
+        Self { successors }
+    }
+
+    pub fn add_edge(&mut self, before: i32, after: i32) {
+        self.successors.entry(after).or_default();
+        let successors = self.successors.entry(before).or_default();
+        if !successors.contains(&after) {
+            successors.push(after);
+        }
+    }
+
+    pub fn remove_edge(&mut self, before: i32, after: i32) {
+        if let Some(successors) = self.successors.get_mut(&before) {
+            successors.retain(|page| *page != after);
+        }
+    }
+
+    /// The graph restricted to the given pages
+    pub fn subgraph(&self, pages: &[i32]) -> Self {
+        let pages: HashSet<i32> = pages.iter().copied().collect();
+        Self {
+            successors: pages
+                .iter()
+                .map(|page| {
+                    let after = self
+                        .successors(*page)
+                        .iter()
+                        .filter(|after| pages.contains(after))
+                        .copied()
+                        .collect();
+                    (*page, after)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn successors(&self, page: i32) -> &[i32] {
+        self.successors.get(&page).map_or(&[], Vec::as_slice)
+    }
+
+    /// The pages that must follow `page`, directly or through other pages
+    pub fn descendants(&self, page: i32) -> HashSet<i32> {
+        let mut descendants = HashSet::new();
+        let mut pending: Vec<i32> = self.successors(page).to_vec();
+        while let Some(next) = pending.pop() {
+            if descendants.insert(next) {
+                pending.extend(self.successors(next));
+            }
+        }
+
+        descendants
+    }
+
+    /// A shortest chain of pages from `from` to `to`, both included
+    pub fn shortest_path(&self, from: i32, to: i32) -> Option<Vec<i32>> {
+        let mut previous: HashMap<i32, i32> = HashMap::new();
+        let mut pending: VecDeque<i32> = VecDeque::from([from]);
+        while let Some(page) = pending.pop_front() {
+            for after in self.successors(page) {
+                if previous.contains_key(after) {
+                    continue;
+                }
+                previous.insert(*after, page);
+                if *after == to {
+                    let mut path = vec![to];
+                    while let Some(before) = path.last().and_then(|page| previous.get(page)) {
+                        path.push(*before);
+                        if *before == from {
+                            break;
+                        }
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                pending.push_back(*after);
+            }
+        }
+
+        None
+    }
+
+    /// Every page that appears in a rule, in increasing order
+    pub fn pages(&self) -> impl Iterator<Item = i32> + '_ {
+        self.successors.keys().copied()
+    }
+
+    /// Every `(before, after)` pair, grouped by `before` in increasing order
+    pub fn edges(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.successors
+            .iter()
+            .flat_map(|(before, after)| after.iter().map(|after| (*before, *after)))
+    }
+
+    /// The graph without the edges implied by longer paths, or `None` if it
+    /// has a cycle, in which case there is no unique reduction
+    pub fn transitive_reduction(&self) -> Option<Self> {
+        if self.find_cycle(&self.pages().collect()).is_some() {
+            return None;
+        }
+
+        let successors = self
+            .successors
+            .iter()
+            .map(|(page, after)| {
+                // Pages reachable in two or more steps do not need a direct edge
+                let indirect: HashSet<i32> = after
+                    .iter()
+                    .flat_map(|next| self.descendants(*next))
+                    .collect();
+
+                let direct = after
+                    .iter()
+                    .filter(|next| !indirect.contains(next))
+                    .copied()
+                    .collect();
+                (*page, direct)
+            })
+            .collect();
+
+        Some(Self { successors })
+    }
+
+    /// One cycle per group of pages that all require each other to come
+    /// first, as a chain of pages starting and ending with the same page
+    pub fn cycles(&self) -> Vec<Vec<i32>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter_map(|component| self.find_cycle(&component))
+            .collect()
+    }
+
+    /// Finds a cycle among `pages`, if there is any
+    pub fn find_cycle(&self, pages: &HashSet<i32>) -> Option<Vec<i32>> {
+        let mut visited: HashSet<i32> = HashSet::new();
+        for start in self.successors.keys().filter(|page| pages.contains(page)) {
+            if visited.contains(start) {
+                continue;
+            }
+
+            let mut path: Vec<i32> = vec![];
+            if let Some(cycle) = self.walk_cycle(*start, pages, &mut visited, &mut path) {
+                return Some(cycle);
+            }
+        }
+
+        None
+    }
+
+    fn walk_cycle(
+        &self,
+        page: i32,
+        pages: &HashSet<i32>,
+        visited: &mut HashSet<i32>,
+        path: &mut Vec<i32>,
+    ) -> Option<Vec<i32>> {
+        if let Some(position) = path.iter().position(|p| *p == page) {
+            let mut cycle = path[position..].to_vec();
+            cycle.push(page);
+            return Some(cycle);
+        }
+        if !visited.insert(page) {
+            return None;
+        }
+
+        path.push(page);
+        for after in self.successors(page) {
+            if pages.contains(after) {
+                if let Some(cycle) = self.walk_cycle(*after, pages, visited, path) {
+                    return Some(cycle);
+                }
+            }
+        }
+        path.pop();
+
+        None
+    }
+
+    /// Groups pages that can all reach each other (Tarjan's algorithm)
+    pub fn strongly_connected_components(&self) -> Vec<HashSet<i32>> {
+        let mut state = Tarjan::default();
+        for page in self.successors.keys() {
+            if !state.index.contains_key(page) {
+                self.tarjan(*page, &mut state);
+            }
+        }
+
+        state.components
+    }
+
+    fn tarjan(&self, page: i32, state: &mut Tarjan) {
+        state.index.insert(page, state.next_index);
+        state.low_link.insert(page, state.next_index);
+        state.next_index += 1;
+        state.stack.push(page);
+        state.on_stack.insert(page);
+
+        for after in self.successors(page) {
+            if !state.index.contains_key(after) {
+                self.tarjan(*after, state);
+                let low = state.low_link[&page].min(state.low_link[after]);
+                state.low_link.insert(page, low);
+            } else if state.on_stack.contains(after) {
+                let low = state.low_link[&page].min(state.index[after]);
+                state.low_link.insert(page, low);
+            }
+        }
+
+        if state.low_link[&page] == state.index[&page] {
+            let mut component = HashSet::new();
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(&member);
+                component.insert(member);
+                if member == page {
+                    break;
+                }
+            }
+            state.components.push(component);
+        }
+    }
+
+    /// Sorts `pages` so that every page comes after the pages that must
+    /// precede it, keeping the original order where the rules allow it.
+    /// Repeated pages are ordered independently, by position. Fails with a
+    /// cycle among the pages if they cannot be ordered.
+    pub fn topological_order(&self, pages: &[i32]) -> Result<Vec<i32>, Vec<i32>> {
+        let subgraph = self.subgraph(pages);
+        let mut missing_before: Vec<usize> = vec![0; pages.len()];
+        for (position, page) in pages.iter().enumerate() {
+            for before in pages {
+                // A page that must come before itself is never placed
+                if subgraph.successors(*before).contains(page) {
+                    missing_before[position] += 1;
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(pages.len());
+        let mut placed: Vec<bool> = vec![false; pages.len()];
+        while order.len() < pages.len() {
+            let Some(next) = (0..pages.len()).find(|p| !placed[*p] && missing_before[*p] == 0)
+            else {
+                let remaining: HashSet<i32> = (0..pages.len())
+                    .filter(|p| !placed[*p])
+                    .map(|p| pages[p])
+                    .collect();
+                // Only a cycle can leave pages unordered, but fall back to
+                // reporting all of them rather than panicking
+                return Err(subgraph
+                    .find_cycle(&remaining)
+                    .unwrap_or_else(|| remaining.into_iter().collect()));
+            };
+
+            placed[next] = true;
+            order.push(pages[next]);
+            for (position, page) in pages.iter().enumerate() {
+                if subgraph.successors(pages[next]).contains(page) {
+                    missing_before[position] -= 1;
+                }
+            }
+        }
+
+        Ok(order)
+    }
+}
+
+#[derive(Default)]
+struct Tarjan {
+    next_index: usize,
+    index: HashMap<i32, usize>,
+    low_link: HashMap<i32, usize>,
+    stack: Vec<i32>,
+    on_stack: HashSet<i32>,
+    components: Vec<HashSet<i32>>,
+}
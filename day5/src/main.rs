@@ -1,29 +1,445 @@
+mod closure;
+mod correction;
+mod dot;
+mod graph;
+mod orderings;
+mod parser;
 mod safety_manual;
 
-use std::{env, fs, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    io::{self, BufRead},
+    path::Path,
+    process,
+};
 
-use safety_manual::SafetyManualUpdates;
+use correction::CorrectionGoal;
+use safety_manual::{
+    MedianError, MedianPolicy, OrderingError, OrderingRule, PagesForUpdate, Precedence,
+    SafetyManualUpdates,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let raw_input: String = read_input(args.get(1).unwrap_or(&String::from("input.txt")));
-    let safety_manual = SafetyManualUpdates::from(raw_input);
+    let mut safety_manual: SafetyManualUpdates = raw_input.parse().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let median_policy = match flag_value(&args, "--median") {
+        Some(policy) => MedianPolicy::try_from(policy).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        }),
+        None => MedianPolicy::default(),
+    };
+
+    match args
+        .get(2)
+        .map(String::as_str)
+        .filter(|arg| !arg.starts_with("--"))
+    {
+        None => {
+            let (safe_sum, errors) = median_sum(safety_manual.correct_updates(), median_policy);
+            for err in errors {
+                eprintln!("Skipping a safe update: {}", err);
+            }
+            println!("Safe updates median sum: {}", safe_sum);
+
+            let mut corrected = vec![];
+            for update in safety_manual.corrected_updates() {
+                match update {
+                    Ok(pages) => corrected.push(pages),
+                    Err(err) => eprintln!("Skipping an unsafe update: {}", err),
+                }
+            }
+            let (corrected_sum, errors) = median_sum(corrected.iter(), median_policy);
+            for err in errors {
+                eprintln!("Skipping a corrected update: {}", err);
+            }
+            println!("Corrected unsafe updates median sum: {}", corrected_sum);
+        }
+        Some("report") => print_report(&safety_manual),
+        Some("cycles") => print_cycles(&safety_manual),
+        Some("dot") => print_dot(&safety_manual, &args[3..]),
+        Some("orderings") => print_orderings(&safety_manual, &args[3..]),
+        Some("minimal") => print_minimal_corrections(&safety_manual, &args[3..]),
+        Some("query") => print_query(&safety_manual, &args[3..]),
+        Some("edit") => edit_rules(&mut safety_manual),
+        Some("stats") => print_stats(&safety_manual),
+        Some(other) => {
+            eprintln!(
+                "Unknown command '{}', expected: report, cycles, dot, orderings, minimal, query, edit, stats",
+                other
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Sums the middle pages of some updates, along with the reasons some of them
+/// have none
+fn median_sum<'a, I>(updates: I, policy: MedianPolicy) -> (f64, Vec<MedianError>)
+where
+    I: IntoIterator<Item = &'a PagesForUpdate>,
+{
+    let mut sum = 0.0;
+    let mut errors = vec![];
+    for pages in updates {
+        match pages.median(policy) {
+            Ok(median) => sum += median,
+            Err(err) => errors.push(err),
+        }
+    }
+
+    (sum, errors)
+}
+
+/// Prints how long the updates are, how many are valid, how many rules they
+/// touch and the middle page sums under each median policy
+fn print_stats(safety_manual: &SafetyManualUpdates) {
+    let updates = safety_manual.pages_for_updates();
+    if updates.is_empty() {
+        println!("No updates");
+        return;
+    }
+
+    let mut lengths: BTreeMap<usize, usize> = BTreeMap::new();
+    for pages in updates {
+        *lengths.entry(pages.len()).or_default() += 1;
+    }
+    println!("Update lengths:");
+    for (length, count) in lengths {
+        println!("  {} pages: {} updates", length, count);
+    }
+
+    let valid = safety_manual.correct_updates();
     println!(
-        "Safe updates median sum: {}",
-        safety_manual
-            .correct_updates()
-            .iter()
-            .map(|i| i.median())
-            .sum::<i32>()
+        "Valid updates: {} of {} ({:.1}%)",
+        valid.len(),
+        updates.len(),
+        100.0 * valid.len() as f64 / updates.len() as f64
     );
+
+    let touched: Vec<usize> = updates
+        .iter()
+        .map(|pages| safety_manual.rules_touched(pages))
+        .collect();
     println!(
-        "Corrected unsafe updates median sum: {}",
-        safety_manual
-            .corrected_updates()
-            .iter()
-            .map(|i| i.median())
-            .sum::<i32>()
+        "Rules touched per update: min {}, mean {:.1}, max {}",
+        touched.iter().min().unwrap(),
+        touched.iter().sum::<usize>() as f64 / touched.len() as f64,
+        touched.iter().max().unwrap()
     );
+
+    let corrected: Vec<PagesForUpdate> = safety_manual
+        .corrected_updates()
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect();
+    println!("Median sums:");
+    for policy in MedianPolicy::ALL {
+        let (valid_sum, valid_errors) = median_sum(valid.iter().copied(), policy);
+        let (corrected_sum, corrected_errors) = median_sum(corrected.iter(), policy);
+        println!(
+            "  {}: valid {}, corrected {}, skipped {}",
+            policy,
+            valid_sum,
+            corrected_sum,
+            valid_errors.len() + corrected_errors.len()
+        );
+    }
+}
+
+/// Prints the rules each update breaks
+fn print_report(safety_manual: &SafetyManualUpdates) {
+    for (line, pages) in safety_manual.pages_for_updates().iter().enumerate() {
+        let violations = safety_manual.validate(pages);
+        if violations.is_empty() {
+            println!("Update {} ({}): valid", line + 1, pages);
+        } else {
+            println!(
+                "Update {} ({}): {} violations",
+                line + 1,
+                pages,
+                violations.len()
+            );
+            for violation in violations {
+                println!("  {}", violation);
+            }
+        }
+    }
+}
+
+/// Prints the contradictions among the rules, and the updates they affect
+fn print_cycles(safety_manual: &SafetyManualUpdates) {
+    if safety_manual.rule_cycles().is_empty() {
+        println!("The ordering rules are consistent");
+    }
+    for cycle in safety_manual.rule_cycles() {
+        println!("Rules cycle: {}", OrderingError::Cycle(cycle.clone()));
+    }
+
+    for (line, pages) in safety_manual.pages_for_updates().iter().enumerate() {
+        if let Some(cycle) = safety_manual.update_cycle(pages) {
+            println!(
+                "Update {} ({}) cannot be ordered: {}",
+                line + 1,
+                pages,
+                OrderingError::Cycle(cycle)
+            );
+        }
+    }
+}
+
+/// Reads `+a|b` and `-a|b` lines from stdin to add and remove rules, and
+/// prints the updates each edit makes valid or invalid
+fn edit_rules(safety_manual: &mut SafetyManualUpdates) {
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Could not read stdin");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (adding, rule) = if let Some(rule) = line.strip_prefix('+') {
+            (true, rule)
+        } else if let Some(rule) = line.strip_prefix('-') {
+            (false, rule)
+        } else {
+            eprintln!("Expected +a|b to add a rule or -a|b to remove one");
+            continue;
+        };
+        let rule: OrderingRule = match rule.parse() {
+            Ok(rule) => rule,
+            Err(err) => {
+                eprintln!("Invalid rule: {}", err);
+                continue;
+            }
+        };
+
+        let change = if adding {
+            match safety_manual.add_rule(rule.clone()) {
+                Ok(change) => change,
+                Err(err) => {
+                    println!("Rejected {}: {}", rule, err);
+                    continue;
+                }
+            }
+        } else if safety_manual.has_rule(rule.before(), rule.after()) {
+            safety_manual.remove_rule(&rule)
+        } else {
+            println!("No rule {} to remove", rule);
+            continue;
+        };
+
+        println!("{} {}", if adding { "Added" } else { "Removed" }, rule);
+        let pages = safety_manual.pages_for_updates();
+        for idx in change.now_valid {
+            println!("  Update {} ({}) is now valid", idx + 1, pages[idx]);
+        }
+        for idx in change.now_invalid {
+            println!("  Update {} ({}) is now invalid", idx + 1, pages[idx]);
+        }
+        println!(
+            "  {} of {} updates valid",
+            safety_manual.correct_updates().len(),
+            pages.len()
+        );
+    }
+}
+
+/// Answers `before <a> <b>`, `ancestors <page>` or `descendants <page>`
+/// queries about what the rules force
+fn print_query(safety_manual: &SafetyManualUpdates, args: &[String]) {
+    let page = |idx: usize| -> i32 {
+        args.get(idx)
+            .and_then(|page| page.parse().ok())
+            .unwrap_or_else(|| {
+                eprintln!("Expected a page number as argument {}", idx + 1);
+                process::exit(1);
+            })
+    };
+    let describe = |pages: BTreeSet<i32>| {
+        pages
+            .iter()
+            .map(|page| page.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+    let explain = |before: i32, after: i32| {
+        let chain = safety_manual
+            .explain(before, after)
+            .expect("Forced pages are reachable through rules");
+        println!(
+            "  because of {}",
+            chain
+                .iter()
+                .map(|rule| rule.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    };
+
+    match args.first().map(String::as_str) {
+        Some("before") => {
+            let (a, b) = (page(1), page(2));
+            match safety_manual.precedence(a, b) {
+                Precedence::Before => {
+                    println!("{} must come before {}", a, b);
+                    explain(a, b);
+                }
+                Precedence::After => {
+                    println!("{} must come after {}", a, b);
+                    explain(b, a);
+                }
+                Precedence::Unordered => println!("The rules do not order {} and {}", a, b),
+                Precedence::Contradictory => {
+                    println!("The rules require {} and {} to each come first", a, b);
+                    explain(a, b);
+                    explain(b, a);
+                }
+            }
+        }
+        Some("ancestors") => {
+            let page = page(1);
+            println!(
+                "Pages forced before {}: {}",
+                page,
+                describe(safety_manual.forced_before(page))
+            );
+        }
+        Some("descendants") => {
+            let page = page(1);
+            println!(
+                "Pages forced after {}: {}",
+                page,
+                describe(safety_manual.forced_after(page))
+            );
+        }
+        _ => {
+            eprintln!("Expected a query: before <a> <b>, ancestors <page>, descendants <page>");
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints the smallest fix for each unsafe update, moving as few pages as
+/// possible, or with `--swaps` swapping as few pairs as possible
+fn print_minimal_corrections(safety_manual: &SafetyManualUpdates, args: &[String]) {
+    let goal = if args.iter().any(|arg| arg == "--swaps") {
+        CorrectionGoal::FewestSwaps
+    } else {
+        CorrectionGoal::FewestMoves
+    };
+
+    for (line, pages) in safety_manual.pages_for_updates().iter().enumerate() {
+        if safety_manual.check(pages) {
+            continue;
+        }
+
+        match safety_manual.correct_minimally(pages, goal) {
+            Ok(correction) => {
+                println!(
+                    "Update {} ({}) -> {}: {} moves, {} swaps",
+                    line + 1,
+                    pages,
+                    correction.pages,
+                    correction.moves.len(),
+                    correction.swaps
+                );
+                for page_move in correction.moves {
+                    println!("  {}", page_move);
+                }
+            }
+            Err(err) => println!("Update {} ({}): {}", line + 1, pages, err),
+        }
+    }
+}
+
+/// Prints how many valid orderings each update has and whether its middle
+/// page is well defined, or lists the orderings of an update given by its
+/// (one based) number
+fn print_orderings(safety_manual: &SafetyManualUpdates, args: &[String]) {
+    if let Some(number) = args.first() {
+        let pages = update_by_number(safety_manual, number);
+        let limit = flag_value(args, "--limit")
+            .map_or(usize::MAX, |limit| limit.parse().expect("Invalid limit"));
+        for ordering in safety_manual.orderings(pages).iter().take(limit) {
+            println!("{}", ordering);
+        }
+        return;
+    }
+
+    for (line, pages) in safety_manual.pages_for_updates().iter().enumerate() {
+        let orderings = safety_manual.orderings(pages);
+        let count = orderings.count();
+        let middle_pages = orderings.middle_pages();
+        match (count, middle_pages) {
+            (Ok(count), Ok(middle_pages)) => println!(
+                "Update {} ({}): {} orderings{}, middle page {}",
+                line + 1,
+                pages,
+                count,
+                if orderings.is_unique() {
+                    " (unique)"
+                } else {
+                    ""
+                },
+                match middle_pages.len() {
+                    0 => String::from("undefined"),
+                    1 => middle_pages.first().unwrap().to_string(),
+                    _ => format!(
+                        "ambiguous between {}",
+                        middle_pages
+                            .iter()
+                            .map(|page| page.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                }
+            ),
+            (Err(err), _) | (_, Err(err)) => println!("Update {} ({}): {}", line + 1, pages, err),
+        }
+    }
+}
+
+/// Prints the rule graph, or the one of an update given by its (one based)
+/// number, in DOT format
+fn print_dot(safety_manual: &SafetyManualUpdates, args: &[String]) {
+    let reduce = args.iter().any(|arg| arg == "--reduce");
+    match args.iter().find(|arg| !arg.starts_with("--")) {
+        None => print!("{}", dot::rules_to_dot(safety_manual, reduce)),
+        Some(number) => {
+            let pages = update_by_number(safety_manual, number);
+            print!("{}", dot::update_to_dot(safety_manual, pages, reduce));
+        }
+    }
+}
+
+/// Looks up an update by its (one based) number, exiting if there is none
+fn update_by_number<'a>(
+    safety_manual: &'a SafetyManualUpdates,
+    number: &str,
+) -> &'a PagesForUpdate {
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_sub(1))
+        .and_then(|idx| safety_manual.pages_for_updates().get(idx))
+        .unwrap_or_else(|| {
+            eprintln!("No update number {}", number);
+            process::exit(1);
+        })
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .map(String::as_str)
 }
 
 fn read_input<P: AsRef<Path>>(file: P) -> String {
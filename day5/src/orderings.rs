@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+
+use crate::safety_manual::{OrderingError, PagesForUpdate, SafetyManualUpdates};
+
+/// The most pages an update can have for its orderings to be counted, as
+/// counting keeps a number for every subset of them
+pub const MAX_COUNTED_PAGES: usize = 20;
+
+/// The ways the pages of an update can be ordered without breaking any rule
+#[derive(Debug, Clone)]
+pub struct UpdateOrderings {
+    pages: Vec<i32>,
+    /// For each page, the positions of the pages that must come before it
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl UpdateOrderings {
+    pub fn new(safety_manual: &SafetyManualUpdates, pages: &PagesForUpdate) -> Self {
+        let predecessors = pages
+            .iter()
+            .map(|page| {
+                pages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| safety_manual.has_rule(**other, *page))
+                    .map(|(idx, _)| idx)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            pages: pages.to_vec(),
+            predecessors,
+        }
+    }
+
+    /// How many valid orderings there are, zero if the rules contradict
+    /// each other
+    pub fn count(&self) -> Result<u64, OrderingError> {
+        let prefixes = self.prefix_counts()?;
+        Ok(prefixes[prefixes.len() - 1])
+    }
+
+    /// Whether there is exactly one valid ordering, which holds when a
+    /// single page can come next at every step
+    pub fn is_unique(&self) -> bool {
+        let mut placed = vec![false; self.pages.len()];
+        for _ in 0..self.pages.len() {
+            let mut available = (0..self.pages.len()).filter(|idx| self.can_place(*idx, &placed));
+            match (available.next(), available.next()) {
+                (Some(idx), None) => placed[idx] = true,
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// The pages that end up in the middle of at least one valid ordering.
+    /// The middle page is well defined when there is exactly one of them, and
+    /// there are none when the rules contradict each other.
+    pub fn middle_pages(&self) -> Result<BTreeSet<i32>, OrderingError> {
+        let prefixes = self.prefix_counts()?;
+        // Prefixes only extend to full orderings when there is at least one
+        if prefixes[prefixes.len() - 1] == 0 {
+            return Ok(BTreeSet::new());
+        }
+        let middle = self.pages.len() / 2;
+        let masks = self.predecessor_masks();
+
+        let mut pages = BTreeSet::new();
+        for (prefix, count) in prefixes.iter().enumerate() {
+            if *count == 0 || prefix.count_ones() as usize != middle {
+                continue;
+            }
+            for (idx, required) in masks.iter().enumerate() {
+                if prefix & (1 << idx) == 0 && prefix & required == *required {
+                    pages.insert(self.pages[idx]);
+                }
+            }
+        }
+
+        Ok(pages)
+    }
+
+    /// Lists the valid orderings lazily, in lexicographic order of the
+    /// original page positions
+    pub fn iter(&self) -> LinearExtensions<'_> {
+        LinearExtensions {
+            orderings: self,
+            placed: vec![false; self.pages.len()],
+            order: vec![],
+            cursor: vec![0],
+            done: false,
+        }
+    }
+
+    /// For each set of pages, as a bit mask of their positions, the number
+    /// of ways they can be ordered at the start of the update
+    fn prefix_counts(&self) -> Result<Vec<u64>, OrderingError> {
+        let n = self.pages.len();
+        if n > MAX_COUNTED_PAGES {
+            return Err(OrderingError::TooManyPages {
+                pages: n,
+                limit: MAX_COUNTED_PAGES,
+            });
+        }
+
+        let masks = self.predecessor_masks();
+        let mut counts = vec![0u64; 1 << n];
+        counts[0] = 1;
+        for prefix in 0..counts.len() {
+            if counts[prefix] == 0 {
+                continue;
+            }
+            for (idx, required) in masks.iter().enumerate() {
+                if prefix & (1 << idx) == 0 && prefix & required == *required {
+                    counts[prefix | (1 << idx)] += counts[prefix];
+                }
+            }
+        }
+
+        Ok(counts)
+    }
+
+    fn predecessor_masks(&self) -> Vec<usize> {
+        self.predecessors
+            .iter()
+            .map(|before| before.iter().fold(0, |mask, idx| mask | (1 << idx)))
+            .collect()
+    }
+
+    fn can_place(&self, idx: usize, placed: &[bool]) -> bool {
+        !placed[idx] && self.predecessors[idx].iter().all(|before| placed[*before])
+    }
+}
+
+/// Iterator over the valid orderings of an update, built one page at a time
+/// with backtracking
+pub struct LinearExtensions<'a> {
+    orderings: &'a UpdateOrderings,
+    placed: Vec<bool>,
+    /// Positions of the pages placed so far
+    order: Vec<usize>,
+    /// For each placed page and the next one, the first position to try there
+    cursor: Vec<usize>,
+    done: bool,
+}
+
+impl LinearExtensions<'_> {
+    fn backtrack(&mut self) {
+        self.cursor.pop();
+        if let Some(idx) = self.order.pop() {
+            self.placed[idx] = false;
+        }
+    }
+}
+
+impl Iterator for LinearExtensions<'_> {
+    type Item = PagesForUpdate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.orderings.pages.len();
+        while !self.done {
+            let depth = self.order.len();
+            if depth == n {
+                let ordering = self
+                    .order
+                    .iter()
+                    .map(|idx| self.orderings.pages[*idx])
+                    .collect();
+                self.backtrack();
+                self.done = n == 0;
+                return Some(ordering);
+            }
+
+            match (self.cursor[depth]..n).find(|idx| self.orderings.can_place(*idx, &self.placed)) {
+                Some(idx) => {
+                    self.cursor[depth] = idx + 1;
+                    self.placed[idx] = true;
+                    self.order.push(idx);
+                    self.cursor.push(0);
+                }
+                None if depth == 0 => self.done = true,
+                None => self.backtrack(),
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::Display;
+
+use crate::safety_manual::{OrderingRule, PagesForUpdate};
+
+/// A line of a safety manual that could not be read, with its (one based)
+/// number
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidRule(String),
+    InvalidUpdate(String),
+    /// A `[name]` header other than `[rules]` and `[updates]`
+    UnknownSection(String),
+    /// An `a|b` line after the updates started, without a `[rules]` header
+    RuleAfterUpdates,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::InvalidRule(reason) => write!(f, "invalid ordering rule, {}", reason),
+            ParseErrorKind::InvalidUpdate(reason) => write!(f, "invalid update, {}", reason),
+            ParseErrorKind::UnknownSection(name) => write!(
+                f,
+                "unknown section '{}', expected [rules] or [updates]",
+                name
+            ),
+            ParseErrorKind::RuleAfterUpdates => write!(
+                f,
+                "ordering rule after the updates, add a [rules] header to mix them"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Rules,
+    Updates,
+}
+
+/// Reads the ordering rules and updates of a safety manual.
+///
+/// Anything after a `#` is a comment, and blank lines are ignored. Without
+/// headers, `a|b` lines are rules and must all come before the `a,b,c`
+/// updates. After a `[rules]` or `[updates]` header, every line is read as
+/// part of that section until the next header.
+pub fn parse(content: &str) -> Result<(Vec<OrderingRule>, Vec<PagesForUpdate>), ParseError> {
+    let mut ordering_rules: Vec<OrderingRule> = vec![];
+    let mut pages_for_updates: Vec<PagesForUpdate> = vec![];
+
+    let mut header: Option<Section> = None;
+    let mut updates_started = false;
+    for (idx, line) in content.lines().enumerate() {
+        let error = |kind| ParseError {
+            line: idx + 1,
+            kind,
+        };
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            header = match name.trim() {
+                "rules" => Some(Section::Rules),
+                "updates" => Some(Section::Updates),
+                other => return Err(error(ParseErrorKind::UnknownSection(other.to_string()))),
+            };
+            continue;
+        }
+
+        let section = match header {
+            Some(section) => section,
+            None if line.contains('|') && updates_started => {
+                return Err(error(ParseErrorKind::RuleAfterUpdates))
+            }
+            None if line.contains('|') => Section::Rules,
+            None => Section::Updates,
+        };
+        match section {
+            Section::Rules => ordering_rules.push(
+                line.parse()
+                    .map_err(|reason| error(ParseErrorKind::InvalidRule(reason)))?,
+            ),
+            Section::Updates => {
+                updates_started = true;
+                pages_for_updates.push(
+                    line.parse()
+                        .map_err(|reason| error(ParseErrorKind::InvalidUpdate(reason)))?,
+                );
+            }
+        }
+    }
+
+    Ok((ordering_rules, pages_for_updates))
+}
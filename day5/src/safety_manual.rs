@@ -1,4 +1,18 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashSet},
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
+
+use crate::{
+    closure::Closure,
+    correction::{Correction, CorrectionGoal},
+    graph::PageGraph,
+    orderings::UpdateOrderings,
+    parser::{self, ParseError},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct OrderingRule {
@@ -6,35 +20,75 @@ pub struct OrderingRule {
     after: i32,
 }
 
-impl<S: AsRef<str>> From<S> for OrderingRule {
-    fn from(value: S) -> Self {
-        let chunks = value.as_ref().split("|").collect::<Vec<&str>>();
-        Self {
-            before: chunks.first()
-                .expect("No chunk")
-                .parse::<i32>()
-                .expect("Not a number"),
-            after: chunks
-                .get(1)
-                .expect("No chunk")
-                .parse::<i32>()
-                .expect("Not a number"),
-        }
+impl OrderingRule {
+    pub fn before(&self) -> i32 {
+        self.before
+    }
+
+    pub fn after(&self) -> i32 {
+        self.after
+    }
+}
+
+impl Display for OrderingRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}|{}", self.before, self.after)
+    }
+}
+
+impl FromStr for OrderingRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (before, after) = s
+            .split_once('|')
+            .ok_or_else(|| format!("expected two page numbers separated by '|', found '{}'", s))?;
+
+        Ok(Self {
+            before: parse_page(before)?,
+            after: parse_page(after)?,
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct PagesForUpdate(Vec<i32>);
 
-impl<S: AsRef<str>> From<S> for PagesForUpdate {
-    fn from(value: S) -> Self {
-        Self(
-            value
-                .as_ref()
-                .split(",")
-                .map(|n| n.parse::<i32>().expect("Not a number"))
-                .collect(),
-        )
+impl FromStr for PagesForUpdate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self(vec![]));
+        }
+
+        s.split(',').map(parse_page).collect()
+    }
+}
+
+fn parse_page(page: &str) -> Result<i32, String> {
+    let page = page.trim();
+    if page.is_empty() {
+        return Err(String::from("missing page number"));
+    }
+
+    page.parse::<i32>()
+        .map_err(|_| format!("'{}' is not a page number", page))
+}
+
+impl FromIterator<i32> for PagesForUpdate {
+    fn from_iter<T: IntoIterator<Item = i32>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Display for PagesForUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pages = self
+            .iter()
+            .map(|page| page.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", pages.join(","))
     }
 }
 
@@ -52,50 +106,207 @@ impl DerefMut for PagesForUpdate {
     }
 }
 
+/// Which page counts as the middle one of an update with an even number of
+/// pages
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MedianPolicy {
+    Lower,
+    /// The page right after the middle, as the puzzle expects
+    #[default]
+    Upper,
+    /// The average of the two middle pages
+    Average,
+    /// Refuse to pick one
+    Error,
+}
+
+impl MedianPolicy {
+    pub const ALL: [MedianPolicy; 4] = [
+        MedianPolicy::Lower,
+        MedianPolicy::Upper,
+        MedianPolicy::Average,
+        MedianPolicy::Error,
+    ];
+}
+
+impl TryFrom<&str> for MedianPolicy {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, String> {
+        match value {
+            "lower" => Ok(MedianPolicy::Lower),
+            "upper" => Ok(MedianPolicy::Upper),
+            "average" => Ok(MedianPolicy::Average),
+            "error" => Ok(MedianPolicy::Error),
+            other => Err(format!(
+                "Unknown median policy '{}', expected one of: lower, upper, average, error",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for MedianPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MedianPolicy::Lower => write!(f, "lower"),
+            MedianPolicy::Upper => write!(f, "upper"),
+            MedianPolicy::Average => write!(f, "average"),
+            MedianPolicy::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Why an update has no middle page
+#[derive(Debug, Clone, PartialEq)]
+pub enum MedianError {
+    Empty,
+    /// The update has an even number of pages and the policy refuses to pick
+    EvenLength(usize),
+}
+
+impl Display for MedianError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MedianError::Empty => write!(f, "the update has no pages"),
+            MedianError::EvenLength(len) => {
+                write!(f, "the update has an even number of pages ({})", len)
+            }
+        }
+    }
+}
+
 impl PagesForUpdate {
-    pub fn median(&self) -> &i32 {
-        self.get(self.len() / 2).expect("No median item")
+    pub fn median(&self, policy: MedianPolicy) -> Result<f64, MedianError> {
+        let len = self.len();
+        if len == 0 {
+            return Err(MedianError::Empty);
+        }
+        if len % 2 == 1 {
+            return Ok(f64::from(self[len / 2]));
+        }
+
+        let (lower, upper) = (f64::from(self[len / 2 - 1]), f64::from(self[len / 2]));
+        match policy {
+            MedianPolicy::Lower => Ok(lower),
+            MedianPolicy::Upper => Ok(upper),
+            MedianPolicy::Average => Ok((lower + upper) / 2.0),
+            MedianPolicy::Error => Err(MedianError::EvenLength(len)),
+        }
     }
 }
 
+/// An ordering rule broken by an update, with the (zero based) positions of
+/// the two pages in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub rule: OrderingRule,
+    pub before_position: usize,
+    pub after_position: usize,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rule {} broken: page {} at position {} comes after page {} at position {}",
+            self.rule,
+            self.rule.before,
+            self.before_position + 1,
+            self.rule.after,
+            self.after_position + 1
+        )
+    }
+}
+
+/// How the rules order two pages, directly or through other pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precedence {
+    Before,
+    After,
+    Unordered,
+    /// The rules force each page to come before the other
+    Contradictory,
+}
+
+/// Why an update cannot be put in the right order
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderingError {
+    /// The rules require each page of the chain to come before the next one,
+    /// and the last page is the first one again
+    Cycle(Vec<i32>),
+    /// The update has too many pages to go through all their orderings
+    TooManyPages { pages: usize, limit: usize },
+}
+
+impl Display for OrderingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderingError::Cycle(chain) => write!(
+                f,
+                "contradictory rules, pages {} must each come first",
+                chain
+                    .iter()
+                    .map(|page| page.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            ),
+            OrderingError::TooManyPages { pages, limit } => write!(
+                f,
+                "the update has {} pages, at most {} are supported",
+                pages, limit
+            ),
+        }
+    }
+}
+
+/// The updates whose validity changed after editing the rules, by index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleChange {
+    pub now_valid: Vec<usize>,
+    pub now_invalid: Vec<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SafetyManualUpdates {
-    ordering_rules: Vec<OrderingRule>,
+    /// Every `(before, after)` pair of pages a rule orders
+    rule_index: HashSet<(i32, i32)>,
     pages_for_updates: Vec<PagesForUpdate>,
+    graph: PageGraph,
+    closure: Closure,
+    rule_cycles: Vec<Vec<i32>>,
 }
 
-impl<S: AsRef<str>> From<S> for SafetyManualUpdates {
-    fn from(value: S) -> Self {
-        let content = value.as_ref();
-        let mut ordering_rules: Vec<OrderingRule> = vec![];
-        let mut pages_for_updates: Vec<PagesForUpdate> = vec![];
+impl FromStr for SafetyManualUpdates {
+    type Err = ParseError;
 
-        let mut parse_phase = 0u8;
-        for line in content.lines() {
-            if line.is_empty() {
-                // Signals that from here onward each line is a page list to
-                // produce for each update
-                parse_phase = 1;
-                continue;
-            }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ordering_rules, pages_for_updates) = parser::parse(s)?;
+        Ok(Self::new(&ordering_rules, pages_for_updates))
+    }
+}
 
-            match parse_phase {
-                0 => ordering_rules.push(OrderingRule::from(line)),
-                1 => {
-                    pages_for_updates.push(PagesForUpdate::from(line));
-                }
-                _ => (),
-            }
-        }
+impl SafetyManualUpdates {
+    pub fn new(ordering_rules: &[OrderingRule], pages_for_updates: Vec<PagesForUpdate>) -> Self {
+        // Rules may contradict each other as a whole, which only matters for
+        // the updates that hold every page of a cycle
+        let graph = PageGraph::new(ordering_rules);
+        let rule_cycles = graph.cycles();
+        let closure = Closure::new(&graph);
+        let rule_index = ordering_rules
+            .iter()
+            .map(|rule| (rule.before, rule.after))
+            .collect();
 
         SafetyManualUpdates {
-            ordering_rules,
+            rule_index,
             pages_for_updates,
+            graph,
+            closure,
+            rule_cycles,
         }
     }
-}
 
-impl SafetyManualUpdates {
     pub fn correct_updates(&self) -> Vec<&PagesForUpdate> {
         self.pages_for_updates
             .iter()
@@ -105,60 +316,251 @@ impl SafetyManualUpdates {
             .collect::<Vec<&PagesForUpdate>>()
     }
 
-    pub fn corrected_updates(&self) -> Vec<PagesForUpdate> {
+    /// Adds a rule, unless the rules already force its pages the other way
+    /// around, in which case the error names the chain of pages it would
+    /// close
+    pub fn add_rule(&mut self, rule: OrderingRule) -> Result<RuleChange, OrderingError> {
+        if self.has_rule(rule.before, rule.after) {
+            return Ok(RuleChange::default());
+        }
+        if rule.before == rule.after || self.closure.precedes(rule.after, rule.before) {
+            let mut chain = vec![rule.before];
+            chain.extend(
+                self.graph
+                    .shortest_path(rule.after, rule.before)
+                    .unwrap_or_else(|| vec![rule.after]),
+            );
+            return Err(OrderingError::Cycle(chain));
+        }
+
+        Ok(self.edit_rule(&rule, |manual| {
+            manual.rule_index.insert((rule.before, rule.after));
+            manual.graph.add_edge(rule.before, rule.after);
+            manual.closure.add_edge(rule.before, rule.after);
+        }))
+    }
+
+    /// Removes a rule, if there is one
+    pub fn remove_rule(&mut self, rule: &OrderingRule) -> RuleChange {
+        if !self.has_rule(rule.before, rule.after) {
+            return RuleChange::default();
+        }
+
+        self.edit_rule(rule, |manual| {
+            manual.rule_index.remove(&(rule.before, rule.after));
+            manual.graph.remove_edge(rule.before, rule.after);
+            manual.closure.remove_edge(&manual.graph, rule.before);
+            // Removing a rule can only break cycles
+            if !manual.rule_cycles.is_empty() {
+                manual.rule_cycles = manual.graph.cycles();
+            }
+        })
+    }
+
+    /// Applies an edit to the rules and reports the updates it affected,
+    /// which can only be the ones holding both pages of the rule
+    fn edit_rule<F: FnOnce(&mut Self)>(&mut self, rule: &OrderingRule, edit: F) -> RuleChange {
+        let affected: Vec<(usize, bool)> = self
+            .pages_for_updates
+            .iter()
+            .enumerate()
+            .filter(|(_, pages)| pages.contains(&rule.before) && pages.contains(&rule.after))
+            .map(|(idx, pages)| (idx, self.check(pages)))
+            .collect();
+
+        edit(self);
+
+        let mut change = RuleChange::default();
+        for (idx, was_valid) in affected {
+            match (was_valid, self.check(&self.pages_for_updates[idx])) {
+                (false, true) => change.now_valid.push(idx),
+                (true, false) => change.now_invalid.push(idx),
+                _ => (),
+            }
+        }
+
+        change
+    }
+
+    /// Puts every unsafe update in the right order, or explains why it cannot
+    pub fn corrected_updates(&self) -> Vec<Result<PagesForUpdate, OrderingError>> {
         self.pages_for_updates
             .iter()
-            .map(|item| (item, self.check(item)))
-            .filter(|result| !result.1)
-            .map(|(incorred_update, _invalid)| {
-                let mut reordered: PagesForUpdate = incorred_update.clone();
-                let relevant_rules = self
-                    .ordering_rules
+            .filter(|item| !self.check(item))
+            .map(|item| self.correct(item))
+            .collect()
+    }
+
+    /// Reorders the pages of an update so that they follow every rule,
+    /// keeping the original order where the rules allow it
+    pub fn correct(&self, pages: &PagesForUpdate) -> Result<PagesForUpdate, OrderingError> {
+        // The fast path below never compares a page with itself
+        if let Some(page) = pages.iter().find(|page| self.has_rule(**page, **page)) {
+            return Err(OrderingError::Cycle(vec![*page, *page]));
+        }
+        if self.is_total_order(pages) {
+            let mut sorted = pages.clone();
+            sorted.sort_by(|a, b| self.compare(*a, *b));
+            return Ok(sorted);
+        }
+
+        self.graph
+            .topological_order(pages)
+            .map(PagesForUpdate)
+            .map_err(OrderingError::Cycle)
+    }
+
+    /// The rules as a graph from each page to the pages that must follow it
+    pub fn graph(&self) -> &PageGraph {
+        &self.graph
+    }
+
+    /// Reorders the pages of an update so that they follow every rule, as
+    /// close as possible to the original order
+    pub fn correct_minimally(
+        &self,
+        pages: &PagesForUpdate,
+        goal: CorrectionGoal,
+    ) -> Result<Correction, OrderingError> {
+        Correction::new(self, pages, goal)
+    }
+
+    /// The valid orderings of the pages of an update
+    pub fn orderings(&self, pages: &PagesForUpdate) -> UpdateOrderings {
+        UpdateOrderings::new(self, pages)
+    }
+
+    /// Chains of pages whose rules contradict each other, one for each group
+    /// of pages tangled together, whether or not an update holds them all
+    pub fn rule_cycles(&self) -> &[Vec<i32>] {
+        &self.rule_cycles
+    }
+
+    /// A chain of pages of the update whose rules contradict each other
+    pub fn update_cycle(&self, pages: &PagesForUpdate) -> Option<Vec<i32>> {
+        let pages: HashSet<i32> = pages.iter().copied().collect();
+        self.graph.find_cycle(&pages)
+    }
+
+    pub fn pages_for_updates(&self) -> &[PagesForUpdate] {
+        &self.pages_for_updates
+    }
+
+    /// How the rules order page `a` relative to page `b`
+    pub fn precedence(&self, a: i32, b: i32) -> Precedence {
+        match (self.closure.precedes(a, b), self.closure.precedes(b, a)) {
+            (true, true) => Precedence::Contradictory,
+            (true, false) => Precedence::Before,
+            (false, true) => Precedence::After,
+            (false, false) => Precedence::Unordered,
+        }
+    }
+
+    /// Every page the rules force before `page`
+    pub fn forced_before(&self, page: i32) -> BTreeSet<i32> {
+        self.closure.ancestors(page).into_iter().collect()
+    }
+
+    /// Every page the rules force after `page`
+    pub fn forced_after(&self, page: i32) -> BTreeSet<i32> {
+        self.closure.descendants(page).into_iter().collect()
+    }
+
+    /// A shortest chain of rules that forces `before` to come before `after`
+    pub fn explain(&self, before: i32, after: i32) -> Option<Vec<OrderingRule>> {
+        let path = self.graph.shortest_path(before, after)?;
+        Some(
+            path.windows(2)
+                .map(|pair| OrderingRule {
+                    before: pair[0],
+                    after: pair[1],
+                })
+                .collect(),
+        )
+    }
+
+    /// How many rules order two pages of the update
+    pub fn rules_touched(&self, pages: &PagesForUpdate) -> usize {
+        pages
+            .iter()
+            .map(|before| {
+                pages
                     .iter()
-                    .filter(|rule| {
-                        reordered.contains(&rule.before) || reordered.contains(&rule.after)
-                    })
-                    .cloned()
-                    .collect::<Vec<OrderingRule>>();
-
-                // The idea: for each number couple in the incorrect update seek if there is
-                // a rule that specifies their ordering and, if so, swap them where needed. Repeat
-                // for all couples.
-                for idx in 0..reordered.len() {
-                    let mut head = reordered[idx];
-                    if idx != reordered.len() - 1 {
-                        let mut repeat = true;
-                        while repeat {
-                            for j in (idx + 1)..reordered.len() {
-                                let other = reordered[j];
-                                if relevant_rules.contains(&OrderingRule {
-                                    before: other,
-                                    after: head,
-                                }) {
-                                    reordered.swap(idx, j);
-                                    head = reordered[idx];
-                                    repeat = true;
-                                    break;
-                                } else {
-                                    repeat = false;
-                                }
-                            }
-                        }
-                    }
-                }
-                reordered
+                    .filter(|after| self.has_rule(*before, **after))
+                    .count()
             })
-            .collect::<Vec<PagesForUpdate>>()
+            .sum()
+    }
+
+    /// Whether a rule requires `before` to come before `after`
+    pub fn has_rule(&self, before: i32, after: i32) -> bool {
+        self.rule_index.contains(&(before, after))
+    }
+
+    /// Orders two pages by the rule between them, if any, so that pages the
+    /// rules do not order compare equal
+    pub fn compare(&self, a: i32, b: i32) -> Ordering {
+        if self.has_rule(a, b) {
+            Ordering::Less
+        } else if self.has_rule(b, a) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    /// Whether the rules order every pair of pages of the update without
+    /// contradicting each other, so that `compare` is a total order on them.
+    /// This holds exactly when each page has a different number of pages
+    /// that must follow it.
+    fn is_total_order(&self, pages: &[i32]) -> bool {
+        let mut followers: HashSet<usize> = HashSet::new();
+        for page in pages {
+            let mut count = 0;
+            for other in pages {
+                if page == other {
+                    continue;
+                }
+                match self.compare(*page, *other) {
+                    Ordering::Less => count += 1,
+                    Ordering::Greater => (),
+                    Ordering::Equal => return false,
+                }
+            }
+            if !followers.insert(count) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Lists every ordering rule the update breaks, in the order the pages
+    /// appear in it
+    pub fn validate(&self, pages: &PagesForUpdate) -> Vec<Violation> {
+        let mut violations = vec![];
+        for (after_position, after) in pages.iter().enumerate() {
+            for (before_position, before) in pages.iter().enumerate().skip(after_position + 1) {
+                if self.has_rule(*before, *after) {
+                    violations.push(Violation {
+                        rule: OrderingRule {
+                            before: *before,
+                            after: *after,
+                        },
+                        before_position,
+                        after_position,
+                    });
+                }
+            }
+        }
+
+        violations
     }
 
     pub fn check(&self, pages: &PagesForUpdate) -> bool {
         for (idx, num) in pages.iter().enumerate() {
             for other_num in &pages[idx..pages.len()] {
-                if self
-                    .ordering_rules
-                    .iter()
-                    .any(|item| item.before == *other_num && item.after == *num)
-                {
+                if self.has_rule(*other_num, *num) {
                     return false;
                 }
             }
@@ -1,73 +1,154 @@
 use rayon::prelude::*;
-use std::{
-    collections::HashSet,
-    env, fs,
-    path::Path,
-    sync::{Arc, Mutex},
-};
+use std::{env, fs, path::Path, process};
 
-use map::{Cell, Direction, Map};
+use map::{Cell, Map, MapOptions, Patrol};
+use optimizer::{Goal, Strategy};
+use overlay::{MapOverlay, VisitBuffer};
+use stats::WalkStats;
 
 mod map;
+mod optimizer;
+mod overlay;
+mod stats;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let raw_input: String = read_input(args.get(1).unwrap_or(&String::from("day6/input.txt")));
-    let mut current_state = Map::from(raw_input.as_str());
-    while current_state.next() {
-        // ...
-    }
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    let options = MapOptions {
+        multi_guard: has_flag("--multi-guard"),
+        wrap_horizontal: has_flag("--wrap") || has_flag("--wrap-horizontal"),
+        wrap_vertical: has_flag("--wrap") || has_flag("--wrap-vertical"),
+        portals: has_flag("--portals"),
+    };
+    let map = Map::parse(raw_input.as_str(), &options).unwrap_or_else(|err| {
+        eprintln!("Invalid map: {}", err);
+        process::exit(1);
+    });
 
-    println!(
-        "Final number of visited cells: {}",
-        current_state
-            .iter()
-            .filter(|cell| **cell == Cell::Visited)
-            .count()
-    );
+    let guard_maps = map.split_guards();
+    for (guard_number, initial_state) in guard_maps.iter().enumerate() {
+        if guard_maps.len() > 1 {
+            println!("Guard #{}", guard_number + 1);
+        }
 
-    // PART 2
-    let n_loops: Arc<Mutex<usize>> = Arc::default();
-    let initial_state = Map::from(raw_input.as_str());
+        // With wrapping edges or portals the guard may never leave the map
+        let mut buffer = VisitBuffer::new(initial_state.len());
+        if MapOverlay::new(initial_state).patrol(&mut buffer) == Patrol::Loop {
+            println!(
+                "The guard never leaves the map, walking in circles over {} cells",
+                buffer.visited().len()
+            );
+            continue;
+        }
 
-    (0..initial_state.len())
-        .par_bridge()
-        .for_each(|test_index| {
-            let mut current_state = initial_state.clone();
+        let mut current_state = initial_state.clone();
+        let mut stats = WalkStats::new(initial_state);
+        while let Some(step) = current_state.step() {
+            stats.record(&step);
+        }
+
+        println!(
+            "Final number of visited cells: {}",
+            current_state
+                .iter()
+                .filter(|cell| **cell == Cell::Visited)
+                .count()
+        );
+
+        if has_flag("--stats") {
+            print_stats(initial_state, &stats);
+        }
 
-            // Only test this cell if it is free
-            if *current_state
-                .get_index(test_index)
+        if let Some(csv_path) = flag_value(&args, "--csv") {
+            let csv_path = if guard_maps.len() > 1 {
+                format!("{}.{}", csv_path, guard_number + 1)
+            } else {
+                csv_path.to_string()
+            };
+            fs::write(&csv_path, stats.to_csv()).expect("Could not write CSV file");
+        }
+
+        // PART 2
+        println!(
+            "Number of loop configurations detected: {}",
+            count_loop_configurations(initial_state)
+        );
+
+        if let Some(k) = flag_value(&args, "--optimize") {
+            let k: usize = k.parse().expect("Invalid number of obstacles");
+            let goal = if has_flag("--minimize") {
+                Goal::Minimize
+            } else {
+                Goal::Maximize
+            };
+            let strategy = match flag_value(&args, "--beam") {
+                Some(width) => Strategy::Beam(width.parse().expect("Invalid beam width")),
+                None => Strategy::Greedy,
+            };
+
+            match optimizer::optimize(initial_state, k, goal, strategy) {
+                Some(placement) => println!(
+                    "Best placement ({:?}, {:?}): {:?} -> {} visited cells",
+                    goal,
+                    strategy,
+                    placement
+                        .obstacles
+                        .iter()
+                        .map(|idx| initial_state.index_to_coords(*idx))
+                        .collect::<Vec<(usize, usize)>>(),
+                    placement.visited
+                ),
+                None => println!("The guard never leaves the map, nothing to optimize"),
+            }
+        }
+    }
+}
+
+fn count_loop_configurations(initial_state: &Map) -> usize {
+    (0..initial_state.len())
+        .into_par_iter()
+        // Only test this cell if it is free
+        .filter(|test_index| {
+            *initial_state
+                .get_index(*test_index)
                 .expect("Out of bounds index")
                 == Cell::Free
-            {
+        })
+        .map_init(
+            || VisitBuffer::new(initial_state.len()),
+            |buffer, test_index| {
+                let mut current_state = MapOverlay::new(initial_state);
                 current_state.set(test_index, Cell::Obstacle);
-                // current_state.pretty_print();
-                // println!("Testing moves");
-                let mut guard_path_buffer: HashSet<(usize, Direction)> = HashSet::new();
-                'walk_guard: while current_state.next() {
-                    if let Some((current_guard_index, cell)) = current_state.get_guard() {
-                        let guard = cell.as_guard();
-                        if let Some(guard) = guard {
-                            if guard_path_buffer
-                                .contains(&(current_guard_index, guard.direction().clone()))
-                            {
-                                *n_loops.lock().unwrap() += 1;
-                                break 'walk_guard;
-                            } else {
-                                guard_path_buffer
-                                    .insert((current_guard_index, guard.direction().clone()));
-                            }
-                        }
-                    }
-                }
-            }
-        });
+                current_state.patrol(buffer) == Patrol::Loop
+            },
+        )
+        .filter(|is_loop| *is_loop)
+        .count()
+}
 
+fn print_stats(map: &Map, stats: &WalkStats) {
+    print!("{}", stats.heatmap(map));
+
+    let run = stats.longest_run();
     println!(
-        "Number of loop configurations detected: {}",
-        n_loops.lock().unwrap()
+        "Longest straight run: {} steps going {} from {:?}",
+        run.length,
+        run.direction,
+        map.index_to_coords(run.start)
     );
+
+    println!("Busiest obstacles:");
+    for (obstacle, turns) in stats.turns().iter().take(10) {
+        println!("  {:?}: {} turns", map.index_to_coords(*obstacle), turns);
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .map(String::as_str)
 }
 
 fn read_input<P: AsRef<Path>>(file: P) -> String {
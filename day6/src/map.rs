@@ -1,5 +1,5 @@
 use rayon::prelude::*;
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
 #[derive(Debug, Clone)]
 pub struct Map {
@@ -7,6 +7,15 @@ pub struct Map {
     height: usize,
     cells: Vec<Cell>,
     guard_idx: Option<usize>,
+    topology: Topology,
+}
+
+/// How the map edges and portals connect cells together
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    wrap_horizontal: bool,
+    wrap_vertical: bool,
+    portals: HashMap<usize, usize>,
 }
 
 impl Map {
@@ -22,6 +31,10 @@ impl Map {
         self.cells.len()
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
     pub fn get_coords(&self, coords: (usize, usize)) -> Option<&Cell> {
         self.cells.get(self.coords_to_index(coords))
     }
@@ -48,70 +61,78 @@ impl Map {
             .map(|idx| (idx, self.cells.get(idx).unwrap()))
     }
 
-    pub fn next(&mut self) -> bool {
-        let maybe_guard = self
-            .guard_idx
-            .map(|idx| (idx, self.cells.get(idx).unwrap()));
-        if let Some((index, guard)) = maybe_guard {
-            let guard = guard.as_guard().expect("Expected Cell::Guard");
-            let guard_direction = guard.direction().clone();
-            let (row, column) = self.index_to_coords(index);
-
-            let next_guard_coords = match guard.direction {
-                Direction::Up => {
-                    if row > 0 {
-                        Some((row - 1, column))
-                    } else {
-                        None
-                    }
-                }
-                Direction::Right => {
-                    if column < self.width - 1 {
-                        Some((row, column + 1))
-                    } else {
-                        None
-                    }
-                }
-                Direction::Down => {
-                    if row < self.height - 1 {
-                        Some((row + 1, column))
-                    } else {
-                        None
-                    }
-                }
-                Direction::Left => {
-                    if column > 0 {
-                        Some((row, column - 1))
-                    } else {
-                        None
-                    }
-                }
-            };
+    /// Moves the guard by one step, returning what happened or `None` if the
+    /// guard already left the map
+    pub fn step(&mut self) -> Option<Step> {
+        let index = self.guard_idx?;
+        let guard = self
+            .cells
+            .get(index)
+            .and_then(Cell::as_guard)
+            .expect("Expected Cell::Guard")
+            .clone();
 
-            if let Some(next_guard_cell_coords) = next_guard_coords {
-                let next_guard_cell = self.get_coords(next_guard_cell_coords).unwrap();
-                if *next_guard_cell == Cell::Obstacle {
-                    self.set(index, Cell::Guard(guard.rotate_right()))
-                } else {
-                    self.set(index, Cell::Visited);
-                    self.set(
-                        self.coords_to_index(next_guard_cell_coords),
-                        Cell::Guard(Guard {
-                            direction: guard_direction,
-                        }),
-                    )
-                }
-            } else {
+        match self.neighbour(index, guard.direction()) {
+            Some(next_index) if self.cells[next_index] == Cell::Obstacle => {
+                let guard = guard.rotate_right();
+                let direction = guard.direction().clone();
+                self.set(index, Cell::Guard(guard));
+                Some(Step::Turned {
+                    at: index,
+                    obstacle: next_index,
+                    direction,
+                })
+            }
+            Some(next_index) => {
+                let direction = guard.direction().clone();
+                self.set(index, Cell::Visited);
+                self.set(next_index, Cell::Guard(guard));
+                Some(Step::Moved {
+                    from: index,
+                    to: next_index,
+                    direction,
+                })
+            }
+            None => {
                 // The guard will go outside the map
                 self.set(index, Cell::Visited);
                 self.guard_idx = None;
+                Some(Step::Exited {
+                    from: index,
+                    direction: guard.direction().clone(),
+                })
             }
-            true
-        } else {
-            false
         }
     }
 
+    /// The index of the cell the guard lands on when moving from `index` in
+    /// the given direction, if any. Depending on the map topology the guard
+    /// may wrap around the edges, and stepping onto a portal takes it straight
+    /// to the paired portal.
+    pub fn neighbour(&self, index: usize, direction: &Direction) -> Option<usize> {
+        let (row, column) = self.index_to_coords(index);
+        let Topology {
+            wrap_horizontal,
+            wrap_vertical,
+            portals,
+        } = &self.topology;
+
+        let coords = match direction {
+            Direction::Up if row > 0 => Some((row - 1, column)),
+            Direction::Up => wrap_vertical.then_some((self.height - 1, column)),
+            Direction::Right if column < self.width - 1 => Some((row, column + 1)),
+            Direction::Right => wrap_horizontal.then_some((row, 0)),
+            Direction::Down if row < self.height - 1 => Some((row + 1, column)),
+            Direction::Down => wrap_vertical.then_some((0, column)),
+            Direction::Left if column > 0 => Some((row, column - 1)),
+            Direction::Left => wrap_horizontal.then_some((row, self.width - 1)),
+        };
+
+        coords
+            .map(|coords| self.coords_to_index(coords))
+            .map(|idx| portals.get(&idx).copied().unwrap_or(idx))
+    }
+
     #[allow(unused)]
     pub fn pretty_print(&self) {
         for i in 0..self.width {
@@ -122,44 +143,212 @@ impl Map {
         }
         println!()
     }
-}
 
-impl From<&str> for Map {
-    fn from(raw: &str) -> Self {
-        let width = raw.lines().nth(0).expect("No first line in input").len();
-        let height = raw.lines().count();
-        let mut cells = vec![];
-        let mut guard_idx = None;
-        let mut i = 0;
-        for line in raw.lines() {
-            for char in line.chars() {
-                let cell = Cell::from(char);
+    /// Parses a map, rejecting unknown glyphs, ragged rows and an unexpected
+    /// number of guards.
+    pub fn parse(raw: &str, options: &MapOptions) -> Result<Self, MapParseError> {
+        let lines: Vec<&str> = raw.trim_end_matches(['\n', '\r']).lines().collect();
+        let width = lines
+            .first()
+            .map(|line| line.chars().count())
+            .filter(|width| *width > 0)
+            .ok_or(MapParseError::Empty)?;
+        let height = lines.len();
+        let mut cells = Vec::with_capacity(width * height);
+        let mut guards = vec![];
+        let mut portal_ends: HashMap<char, Vec<usize>> = HashMap::new();
+
+        for (row, line) in lines.iter().enumerate() {
+            let mut line_width = 0;
+            for (column, char) in line.chars().enumerate() {
+                let cell = if options.portals && Cell::is_portal_glyph(char) {
+                    portal_ends.entry(char).or_default().push(cells.len());
+                    Cell::Portal(char)
+                } else {
+                    Cell::try_from(char).map_err(|_| MapParseError::UnknownGlyph {
+                        glyph: char,
+                        line: row + 1,
+                        column: column + 1,
+                    })?
+                };
                 if cell.is_guard() {
-                    guard_idx = Some(i);
+                    guards.push(cells.len());
                 }
                 cells.push(cell);
-                i += 1;
+                line_width += 1;
+            }
+
+            if line_width != width {
+                return Err(MapParseError::RaggedRow {
+                    line: row + 1,
+                    expected: width,
+                    found: line_width,
+                });
             }
         }
 
-        Self {
+        if guards.is_empty() {
+            return Err(MapParseError::NoGuard);
+        }
+        if guards.len() > 1 && !options.multi_guard {
+            return Err(MapParseError::MultipleGuards(guards.len()));
+        }
+
+        let mut portals = HashMap::new();
+        for (glyph, ends) in portal_ends {
+            match ends.as_slice() {
+                [a, b] => {
+                    portals.insert(*a, *b);
+                    portals.insert(*b, *a);
+                }
+                _ => {
+                    return Err(MapParseError::UnpairedPortal {
+                        glyph,
+                        count: ends.len(),
+                    })
+                }
+            }
+        }
+
+        Ok(Self {
             width,
             height,
             cells,
-            guard_idx,
+            guard_idx: guards.first().copied(),
+            topology: Topology {
+                wrap_horizontal: options.wrap_horizontal,
+                wrap_vertical: options.wrap_vertical,
+                portals,
+            },
+        })
+    }
+
+    /// Splits a map with several guards into one map per guard, where every
+    /// other guard's starting cell is considered free.
+    pub fn split_guards(&self) -> Vec<Map> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.is_guard())
+            .map(|(guard_idx, _)| {
+                let mut map = self.clone();
+                for (idx, cell) in map.cells.iter_mut().enumerate() {
+                    if cell.is_guard() && idx != guard_idx {
+                        *cell = Cell::Free;
+                    }
+                }
+                map.guard_idx = Some(guard_idx);
+                map
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// The guard moved forward to the next cell
+    Moved {
+        from: usize,
+        to: usize,
+        direction: Direction,
+    },
+    /// The guard faced an obstacle and turned right, now facing `direction`
+    Turned {
+        at: usize,
+        obstacle: usize,
+        direction: Direction,
+    },
+    /// The guard walked off the map
+    Exited { from: usize, direction: Direction },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Patrol {
+    /// The guard left the map after visiting this many distinct cells
+    Exited { visited: usize },
+    /// The guard is stuck in a loop and will never leave the map
+    Loop,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MapOptions {
+    /// Accept maps with more than one guard on them
+    pub multi_guard: bool,
+    /// Walking off the left or right edge enters the map from the other side
+    pub wrap_horizontal: bool,
+    /// Walking off the top or bottom edge enters the map from the other side
+    pub wrap_vertical: bool,
+    /// Letters (other than `v` and `X`) mark pairs of portals
+    pub portals: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapParseError {
+    Empty,
+    UnknownGlyph {
+        glyph: char,
+        line: usize,
+        column: usize,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    NoGuard,
+    MultipleGuards(usize),
+    UnpairedPortal {
+        glyph: char,
+        count: usize,
+    },
+}
+
+impl Display for MapParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapParseError::Empty => write!(f, "The map is empty"),
+            MapParseError::UnknownGlyph {
+                glyph,
+                line,
+                column,
+            } => write!(f, "Unknown glyph '{glyph}' at line {line}, column {column}"),
+            MapParseError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Line {line} is {found} cells wide, expected {expected} like the first line"
+            ),
+            MapParseError::NoGuard => write!(f, "No guard found on the map"),
+            MapParseError::MultipleGuards(count) => write!(
+                f,
+                "Found {count} guards on the map, but multi guard mode is not enabled"
+            ),
+            MapParseError::UnpairedPortal { glyph, count } => write!(
+                f,
+                "Portal '{glyph}' appears {count} times, portals must come in pairs"
+            ),
         }
     }
 }
 
+impl std::error::Error for MapParseError {}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Cell {
     Obstacle,
     Guard(Guard),
     Free,
     Visited,
+    Portal(char),
 }
 
 impl Cell {
+    pub fn is_portal_glyph(glyph: char) -> bool {
+        glyph.is_ascii_alphabetic() && glyph != 'v' && glyph != 'X'
+    }
+
     pub fn is_guard(&self) -> bool {
         matches!(self, Cell::Guard(_))
     }
@@ -174,28 +363,27 @@ impl Cell {
 
 impl Display for Cell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Cell::Obstacle => "#",
-                Cell::Guard(guard) => guard.direction.symbol(),
-                Cell::Free => ".",
-                Cell::Visited => "X",
-            }
-        )
+        match self {
+            Cell::Obstacle => write!(f, "#"),
+            Cell::Guard(guard) => write!(f, "{}", guard.direction),
+            Cell::Free => write!(f, "."),
+            Cell::Visited => write!(f, "X"),
+            Cell::Portal(glyph) => write!(f, "{}", glyph),
+        }
     }
 }
 
-impl From<char> for Cell {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for Cell {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            '#' => Cell::Obstacle,
-            '.' => Cell::Free,
-            'X' => Cell::Visited,
-            direction => Cell::Guard(Guard {
-                direction: direction.into(),
-            }),
+            '#' => Ok(Cell::Obstacle),
+            '.' => Ok(Cell::Free),
+            'X' => Ok(Cell::Visited),
+            direction => Ok(Cell::Guard(Guard {
+                direction: direction.try_into()?,
+            })),
         }
     }
 }
@@ -231,11 +419,21 @@ impl Direction {
         match self {
             Direction::Up => "^",
             Direction::Right => ">",
-            Direction::Down => "⌄",
+            Direction::Down => "v",
             Direction::Left => "<",
         }
     }
 
+    /// A distinct bit per direction, to pack sets of directions in a `u8`
+    pub fn bit(&self) -> u8 {
+        match self {
+            Direction::Up => 1,
+            Direction::Right => 2,
+            Direction::Down => 4,
+            Direction::Left => 8,
+        }
+    }
+
     pub fn rotate_right(&self) -> Direction {
         match self {
             Direction::Up => Direction::Right,
@@ -246,14 +444,16 @@ impl Direction {
     }
 }
 
-impl From<char> for Direction {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for Direction {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            '^' => Direction::Up,
-            '⌄' => Direction::Down,
-            '<' => Direction::Left,
-            '>' => Direction::Right,
-            _ => panic!("Unexpected direction character"),
+            '^' => Ok(Direction::Up),
+            'v' | '⌄' => Ok(Direction::Down),
+            '<' => Ok(Direction::Left),
+            '>' => Ok(Direction::Right),
+            other => Err(other),
         }
     }
 }
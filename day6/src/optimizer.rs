@@ -0,0 +1,123 @@
+use rayon::prelude::*;
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::{
+    map::{Cell, Map, Patrol},
+    overlay::{MapOverlay, VisitBuffer},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// Make the guard visit as many distinct cells as possible
+    Maximize,
+    /// Make the guard leave the map as soon as possible
+    Minimize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Place one obstacle at a time, always taking the best one
+    Greedy,
+    /// Keep the best `n` partial placements around at each round
+    Beam(usize),
+}
+
+/// A set of extra obstacles and the number of distinct cells the guard
+/// visits before leaving the map when they are in place
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub obstacles: Vec<usize>,
+    pub visited: usize,
+    path: Vec<usize>,
+}
+
+/// Searches for up to `k` extra obstacles that push the guard path length
+/// towards `goal`, keeping the best placement of any round, including the
+/// one without obstacles. Placements that trap the guard in a loop are
+/// discarded.
+///
+/// Only cells on the current guard path are candidates, since an obstacle
+/// anywhere else would not change the walk at all.
+pub fn optimize(map: &Map, k: usize, goal: Goal, strategy: Strategy) -> Option<Placement> {
+    let beam_width = match strategy {
+        Strategy::Greedy => 1,
+        Strategy::Beam(width) => width.max(1),
+    };
+
+    let mut best = evaluate(map, vec![], &mut VisitBuffer::new(map.len()))?;
+    let mut beam = vec![best.clone()];
+    for _ in 0..k {
+        let mut seen: HashSet<Vec<usize>> = HashSet::new();
+        let mut candidates = beam
+            .par_iter()
+            .flat_map(|placement| {
+                placement.path.par_iter().filter_map(|idx| {
+                    if placement.obstacles.contains(idx) {
+                        return None;
+                    }
+                    let mut obstacles = placement.obstacles.clone();
+                    obstacles.push(*idx);
+                    obstacles.sort_unstable();
+                    Some(obstacles)
+                })
+            })
+            .collect::<Vec<Vec<usize>>>();
+        candidates.retain(|obstacles| seen.insert(obstacles.clone()));
+
+        let mut next_beam = candidates
+            .into_par_iter()
+            .map_init(
+                || VisitBuffer::new(map.len()),
+                |buffer, obstacles| evaluate(map, obstacles, buffer),
+            )
+            .flatten()
+            .collect::<Vec<Placement>>();
+        if next_beam.is_empty() {
+            break;
+        }
+
+        sort_by_goal(&mut next_beam, goal);
+        next_beam.truncate(beam_width);
+        // Ties keep the earlier placement, which has fewer obstacles
+        if compare_visited(&next_beam[0], &best, goal).is_lt() {
+            best = next_beam[0].clone();
+        }
+        beam = next_beam;
+    }
+
+    Some(best)
+}
+
+/// Orders placements from the best to the worst for `goal`
+fn compare_visited(a: &Placement, b: &Placement, goal: Goal) -> Ordering {
+    match goal {
+        Goal::Maximize => b.visited.cmp(&a.visited),
+        Goal::Minimize => a.visited.cmp(&b.visited),
+    }
+}
+
+fn sort_by_goal(placements: &mut [Placement], goal: Goal) {
+    placements
+        .sort_by(|a, b| compare_visited(a, b, goal).then_with(|| a.obstacles.cmp(&b.obstacles)));
+}
+
+fn evaluate(map: &Map, obstacles: Vec<usize>, buffer: &mut VisitBuffer) -> Option<Placement> {
+    let mut state = MapOverlay::new(map);
+    for idx in obstacles.iter() {
+        state.set(*idx, Cell::Obstacle);
+    }
+
+    match state.patrol(buffer) {
+        Patrol::Exited { visited } => {
+            // The guard starting cell is always the first one visited
+            let path = buffer.visited().iter().skip(1).copied().collect();
+
+            Some(Placement {
+                obstacles,
+                visited,
+                path,
+            })
+        }
+        Patrol::Loop => None,
+    }
+}
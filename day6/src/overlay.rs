@@ -0,0 +1,111 @@
+use crate::map::{Cell, Direction, Map, Patrol};
+
+/// A lightweight view over an immutable map that only records the cells that
+/// differ from it, together with its own guard state
+#[derive(Debug, Clone)]
+pub struct MapOverlay<'a> {
+    base: &'a Map,
+    changes: Vec<(usize, Cell)>,
+    guard: Option<(usize, Direction)>,
+}
+
+impl<'a> MapOverlay<'a> {
+    pub fn new(base: &'a Map) -> Self {
+        let guard = base.get_guard().map(|(idx, cell)| {
+            let direction = cell.as_guard().unwrap().direction().clone();
+            (idx, direction)
+        });
+
+        Self {
+            base,
+            changes: vec![],
+            guard,
+        }
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<&Cell> {
+        self.changes
+            .iter()
+            .rev()
+            .find(|(idx, _)| *idx == index)
+            .map(|(_, cell)| cell)
+            .or_else(|| self.base.get_index(index))
+    }
+
+    pub fn set(&mut self, index: usize, value: Cell) {
+        if let Some(guard) = value.as_guard() {
+            self.guard = Some((index, guard.direction().clone()));
+        }
+        self.changes.push((index, value));
+    }
+
+    /// Walks the guard until it leaves the map or starts walking in circles,
+    /// keeping track of the walk in `buffer`. The overlay itself is left
+    /// untouched, so it can be walked again.
+    pub fn patrol(&self, buffer: &mut VisitBuffer) -> Patrol {
+        buffer.clear();
+        let Some((mut index, mut direction)) = self.guard.clone() else {
+            return Patrol::Exited { visited: 0 };
+        };
+
+        buffer.visit(index, &direction);
+        while let Some(next_index) = self.base.neighbour(index, &direction) {
+            if *self.get_index(next_index).unwrap() == Cell::Obstacle {
+                direction = direction.rotate_right();
+            } else {
+                index = next_index;
+            }
+
+            if !buffer.visit(index, &direction) {
+                return Patrol::Loop;
+            }
+        }
+
+        Patrol::Exited {
+            visited: buffer.visited().len(),
+        }
+    }
+}
+
+/// Bookkeeping of a guard walk that can be reused across walks on maps of the
+/// same size without reallocating
+#[derive(Debug, Clone)]
+pub struct VisitBuffer {
+    states: Vec<u8>,
+    visited: Vec<usize>,
+}
+
+impl VisitBuffer {
+    pub fn new(len: usize) -> Self {
+        Self {
+            states: vec![0; len],
+            visited: vec![],
+        }
+    }
+
+    /// The cells visited during the last walk, in visiting order
+    pub fn visited(&self) -> &[usize] {
+        &self.visited
+    }
+
+    fn clear(&mut self) {
+        for idx in self.visited.drain(..) {
+            self.states[idx] = 0;
+        }
+    }
+
+    /// Records the guard standing on `index` facing `direction`, returning
+    /// `false` if it already did before
+    fn visit(&mut self, index: usize, direction: &Direction) -> bool {
+        let bit = direction.bit();
+        let state = &mut self.states[index];
+        if *state & bit != 0 {
+            return false;
+        }
+        if *state == 0 {
+            self.visited.push(index);
+        }
+        *state |= bit;
+        true
+    }
+}
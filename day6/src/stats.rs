@@ -0,0 +1,159 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::map::{Cell, Direction, Map, Step};
+
+/// A straight stretch of the guard walk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub start: usize,
+    /// The number of moves, one less than the cells the run covers
+    pub length: usize,
+    pub direction: Direction,
+}
+
+/// Per cell statistics collected while the guard walks the map
+#[derive(Debug, Clone)]
+pub struct WalkStats {
+    width: usize,
+    visits: Vec<usize>,
+    directions: Vec<u8>,
+    turns: HashMap<usize, usize>,
+    current_run: Run,
+    longest_run: Run,
+}
+
+impl WalkStats {
+    /// Starts collecting statistics for a map, counting the guard starting
+    /// cell as visited once
+    pub fn new(map: &Map) -> Self {
+        let (start, guard) = map.get_guard().expect("No guard on the map");
+        let direction = guard.as_guard().unwrap().direction().clone();
+        let mut visits = vec![0; map.len()];
+        let mut directions = vec![0; map.len()];
+        visits[start] = 1;
+        directions[start] = direction.bit();
+
+        let run = Run {
+            start,
+            length: 0,
+            direction,
+        };
+
+        Self {
+            width: map.width(),
+            visits,
+            directions,
+            turns: HashMap::new(),
+            current_run: run.clone(),
+            longest_run: run,
+        }
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        match step {
+            Step::Moved { to, direction, .. } => {
+                self.visits[*to] += 1;
+                self.directions[*to] |= direction.bit();
+                self.current_run.length += 1;
+                if self.current_run.length > self.longest_run.length {
+                    self.longest_run = self.current_run.clone();
+                }
+            }
+            Step::Turned {
+                at,
+                obstacle,
+                direction,
+            } => {
+                *self.turns.entry(*obstacle).or_default() += 1;
+                self.directions[*at] |= direction.bit();
+                self.current_run = Run {
+                    start: *at,
+                    length: 0,
+                    direction: direction.clone(),
+                };
+            }
+            Step::Exited { .. } => (),
+        }
+    }
+
+    /// The directions the guard faced while crossing the given cell
+    pub fn directions(&self, index: usize) -> Vec<Direction> {
+        ALL_DIRECTIONS
+            .iter()
+            .filter(|direction| self.directions[index] & direction.bit() != 0)
+            .cloned()
+            .collect()
+    }
+
+    /// Obstacles the guard turned at, sorted by the number of turns, busiest first
+    pub fn turns(&self) -> Vec<(usize, usize)> {
+        let mut turns = self
+            .turns
+            .iter()
+            .map(|(index, count)| (*index, *count))
+            .collect::<Vec<(usize, usize)>>();
+        turns.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        turns
+    }
+
+    pub fn longest_run(&self) -> &Run {
+        &self.longest_run
+    }
+
+    /// One line per visited cell or hit obstacle, as
+    /// `row,column,visits,directions,turns`
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("row,column,visits,directions,turns\n");
+        for index in 0..self.visits.len() {
+            let turns = self.turns.get(&index).copied().unwrap_or_default();
+            if self.visits[index] == 0 && turns == 0 {
+                continue;
+            }
+
+            let directions: String = self
+                .directions(index)
+                .iter()
+                .map(Direction::symbol)
+                .collect();
+            writeln!(
+                csv,
+                "{},{},{},{},{}",
+                index / self.width,
+                index % self.width,
+                self.visits[index],
+                directions,
+                turns
+            )
+            .unwrap();
+        }
+
+        csv
+    }
+
+    /// Renders the visit count of each cell of `map`, with `+` standing for
+    /// ten visits or more
+    pub fn heatmap(&self, map: &Map) -> String {
+        let mut out = String::new();
+        for (index, visits) in self.visits.iter().enumerate() {
+            let glyph = match (map.get_index(index), visits) {
+                (Some(Cell::Obstacle), _) => '#',
+                (_, 0) => '.',
+                (_, 1..=9) => char::from_digit(*visits as u32, 10).unwrap(),
+                _ => '+',
+            };
+            out.push(glyph);
+            if (index + 1) % self.width == 0 {
+                out.push('\n');
+            }
+        }
+
+        out
+    }
+}
+
+const ALL_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
@@ -1,31 +1,47 @@
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use common::combinatorics::CartesianPower;
+use rayon::iter::ParallelIterator;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    ops::Bound::{Excluded, Unbounded},
+};
 
-#[derive(Debug, Clone)]
-pub enum Operation {
-    Sum,
-    Mul,
-    Concat,
-}
+use crate::{
+    evaluation::{EvaluationMode, Expression, PrecedenceTable},
+    operator::{Number, Operation, Preimage},
+};
 
-type Number = u64;
+/// A sequence of operations that solves a calibration equation, possibly with
+/// the operands grouped by parentheses
+#[derive(Clone, Debug)]
+pub struct Solution {
+    result: Number,
+    operands: Vec<Number>,
+    operations: Vec<Operation>,
+    grouping: Option<Expression>,
+}
 
-impl Operation {
-    pub fn apply(&self, n: Number, m: Number) -> Number {
-        match self {
-            Operation::Sum => n + m,
-            Operation::Mul => n * m,
-            Operation::Concat => format!("{n}{m}")
-                .parse::<Number>()
-                .expect("Invalid operation result"),
-        }
+impl Solution {
+    #[allow(unused)]
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
     }
+}
 
-    pub fn neutral_operator(&self) -> Number {
-        match self {
-            Operation::Sum => 0,
-            Operation::Mul => 1,
-            Operation::Concat => 0,
+impl Display for Solution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} =", self.result)?;
+        if let Some(grouping) = &self.grouping {
+            return write!(f, " {}", grouping);
         }
+        if let Some(first) = self.operands.first() {
+            write!(f, " {}", first)?;
+        }
+        for (operation, operand) in self.operations.iter().zip(self.operands.iter().skip(1)) {
+            write!(f, " {} {}", operation, operand)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -40,91 +56,435 @@ impl CalibrationEquation {
         self.result
     }
 
-    pub fn try_solve(&self, supported_operations: &[Operation]) -> Option<Vec<Operation>> {
-        let operation_permutations = Permutations::new(supported_operations, self.operands.len());
-        operation_permutations
-            .par_bridge()
-            .find_first(|p| self.is_solved_by(p))
+    /// Looks for a sequence of operations that solves the equation when
+    /// evaluated according to `mode`. With precedences every sequence is
+    /// tried, so equations that are not [enumerable](Self::is_enumerable)
+    /// are never solved.
+    pub fn try_solve_with(
+        &self,
+        supported_operations: &[Operation],
+        mode: &EvaluationMode,
+    ) -> Option<Solution> {
+        match mode {
+            EvaluationMode::LeftToRight => self.try_solve(supported_operations),
+            EvaluationMode::Precedence(table) => {
+                self.try_solve_with_precedence(supported_operations, table)
+            }
+            EvaluationMode::AnyGrouping => {
+                let grouping =
+                    Expression::find_grouping(&self.operands, supported_operations, self.result)?;
+                let mut solution = self.solution(grouping.operations());
+                solution.grouping = Some(grouping);
+                Some(solution)
+            }
+        }
     }
 
-    fn is_solved_by(&self, operation_sequence: &[Operation]) -> bool {
-        if self.operands.is_empty() && self.result != 0 {
-            false
+    fn try_solve_with_precedence(
+        &self,
+        supported_operations: &[Operation],
+        table: &PrecedenceTable,
+    ) -> Option<Solution> {
+        self.operation_sequences(supported_operations)?
+            .par_iter()
+            .find_first(|p| table.evaluate(&self.operands, p) == Some(self.result))
+            .map(|operation_sequence| self.solution(operation_sequence))
+    }
+
+    /// Looks for a sequence of operations that solves the equation. When all
+    /// the operations can be undone this works backwards from the result,
+    /// undoing one operation at a time, otherwise it tries the operations
+    /// forwards from the first operand.
+    pub fn try_solve(&self, supported_operations: &[Operation]) -> Option<Solution> {
+        let last = self.operands.len().checked_sub(1)?;
+        let invertible = supported_operations.iter().all(|operation| {
+            operation
+                .inverse(self.result, self.operands[last])
+                .is_some()
+        });
+
+        let operation_sequence = if invertible {
+            self.solve_backwards(self.result, last, supported_operations)
         } else {
-            let mut acc = operation_sequence.first().unwrap().neutral_operator();
-            for (i, operand) in self.operands.iter().enumerate() {
-                let operation = operation_sequence.get(i).unwrap();
-                acc = operation.apply(acc, *operand);
+            self.solve_forwards(
+                self.operands[0],
+                1,
+                self.operands.len(),
+                Some(self.result),
+                supported_operations,
+            )
+        }?;
+
+        Some(self.solution(operation_sequence))
+    }
+
+    /// Finds the operations to apply to `operands[1..=idx]` so that they
+    /// produce `target`
+    fn solve_backwards(
+        &self,
+        target: Number,
+        idx: usize,
+        supported_operations: &[Operation],
+    ) -> Option<Vec<Operation>> {
+        if idx == 0 {
+            return (self.operands[0] == target).then(Vec::new);
+        }
+
+        let operand = self.operands[idx];
+        for operation in supported_operations {
+            let prefix = match operation.inverse(target, operand) {
+                Some(Preimage::One(previous)) => {
+                    self.solve_backwards(previous, idx - 1, supported_operations)
+                }
+                // Any value works, as long as the operands before can be
+                // combined without overflowing
+                Some(Preimage::Any) => {
+                    self.solve_forwards(self.operands[0], 1, idx, None, supported_operations)
+                }
+                Some(Preimage::None) | None => None,
+            };
+
+            if let Some(mut operation_sequence) = prefix {
+                operation_sequence.push(operation.clone());
+                return Some(operation_sequence);
             }
+        }
+
+        None
+    }
 
-            self.result == acc
+    /// Finds the operations to apply to `operands[idx..end]`, starting from
+    /// `acc`, so that they produce `target`, or any value without one
+    fn solve_forwards(
+        &self,
+        acc: Number,
+        idx: usize,
+        end: usize,
+        target: Option<Number>,
+        supported_operations: &[Operation],
+    ) -> Option<Vec<Operation>> {
+        if idx == end {
+            return target.is_none_or(|target| acc == target).then(Vec::new);
         }
+
+        let operand = self.operands[idx];
+        supported_operations.iter().find_map(|operation| {
+            let acc = operation.apply(acc, operand)?;
+            let mut operation_sequence =
+                self.solve_forwards(acc, idx + 1, end, target, supported_operations)?;
+            operation_sequence.insert(0, operation.clone());
+            Some(operation_sequence)
+        })
     }
-}
 
-impl<T: AsRef<str>> From<T> for CalibrationEquation {
-    fn from(value: T) -> Self {
-        let mut chunks = value.as_ref().split(":");
-        let result = chunks.next().expect("No result chunk found");
-        let operands = chunks.next().expect("No result chunk found").trim();
+    /// Whether every sequence of operations between the operands can be
+    /// enumerated, which searches that try all of them need. Without operands
+    /// there is nothing to enumerate.
+    pub fn is_enumerable(&self, supported_operations: &[Operation]) -> bool {
+        self.operands.is_empty() || self.operation_sequences(supported_operations).is_some()
+    }
 
-        Self {
-            result: result.parse().expect("Invalid result"),
-            operands: operands
-                .split(" ")
-                .map(|v| v.parse().expect("Invalid operand"))
-                .collect::<Vec<Number>>(),
+    /// Every sequence of operations between the operands, or `None` if there
+    /// are no operands or too many sequences to count
+    fn operation_sequences(
+        &self,
+        supported_operations: &[Operation],
+    ) -> Option<CartesianPower<Operation>> {
+        let length = self.operands.len().checked_sub(1)?;
+        CartesianPower::try_new(supported_operations, length)
+    }
+
+    /// Looks for a sequence of operations that solves the equation by trying
+    /// all of them. Equations with too many sequences to count are never
+    /// solved, see [`Self::is_enumerable`].
+    pub fn try_solve_brute_force(&self, supported_operations: &[Operation]) -> Option<Solution> {
+        self.operation_sequences(supported_operations)?
+            .par_iter()
+            .find_first(|p| self.is_solved_by(p))
+            .map(|operation_sequence| self.solution(operation_sequence))
+    }
+
+    /// Counts the sequences of operations that solve the equation when
+    /// evaluated left to right
+    pub fn count_solutions(&self, supported_operations: &[Operation]) -> u128 {
+        match self.operands.first() {
+            Some(first) => count_from(self, supported_operations, *first, 1, &mut HashMap::new()),
+            None => 0,
         }
     }
+
+    /// Lazily lists every sequence of operations that solves the equation
+    /// when evaluated left to right
+    pub fn solutions<'a>(&'a self, supported_operations: &'a [Operation]) -> Solutions<'a> {
+        let mut solutions = Solutions {
+            equation: self,
+            supported_operations,
+            memo: HashMap::new(),
+            stack: vec![],
+            path: vec![],
+        };
+
+        if let Some(first) = self.operands.first() {
+            if solutions.count_from(*first, 1) > 0 {
+                solutions.stack.push(Frame {
+                    acc: *first,
+                    next_operation: 0,
+                });
+            }
+        }
+
+        solutions
+    }
+
+    /// Computes the values the operands can produce when evaluated left to
+    /// right, each with one way to produce it.
+    ///
+    /// When all the operations are monotone, values that overshoot the result
+    /// can only keep growing, so at each step only the smallest of them is
+    /// kept: the values above the result are not all there, but the closest
+    /// one is.
+    pub fn reachable_values(
+        &self,
+        supported_operations: &[Operation],
+    ) -> BTreeMap<Number, Solution> {
+        let Some(first) = self.operands.first() else {
+            return BTreeMap::new();
+        };
+        let monotone = supported_operations
+            .iter()
+            .all(|operation| operation.is_monotone());
+
+        // Each layer holds the values reachable after an operand, with the
+        // index of the value in the previous layer and the operation that
+        // produced them
+        let mut layers: Vec<Vec<(Number, usize, usize)>> = vec![vec![(*first, 0, 0)]];
+        for (idx, operand) in self.operands.iter().enumerate().skip(1) {
+            // Multiplying by zero could still bring an overshooting value back
+            let prune = monotone && !self.operands[idx..].contains(&0);
+            let mut seen: HashMap<Number, usize> = HashMap::new();
+            let mut layer = vec![];
+            let mut smallest_above: Option<(Number, usize, usize)> = None;
+
+            for (previous_idx, (acc, _, _)) in layers[idx - 1].iter().enumerate() {
+                for (operation_idx, operation) in supported_operations.iter().enumerate() {
+                    let Some(value) = operation.apply(*acc, *operand) else {
+                        continue;
+                    };
+                    if prune && value > self.result {
+                        if smallest_above.is_none_or(|(smallest, _, _)| value < smallest) {
+                            smallest_above = Some((value, previous_idx, operation_idx));
+                        }
+                        continue;
+                    }
+                    seen.entry(value).or_insert_with(|| {
+                        layer.push((value, previous_idx, operation_idx));
+                        layer.len() - 1
+                    });
+                }
+            }
+
+            layer.extend(smallest_above);
+            layers.push(layer);
+        }
+
+        let last = layers.len() - 1;
+        layers[last]
+            .iter()
+            .enumerate()
+            .map(|(value_idx, (value, _, _))| {
+                let mut operations = vec![];
+                let mut position = value_idx;
+                for layer in layers[1..].iter().rev() {
+                    let (_, previous_idx, operation_idx) = layer[position];
+                    operations.push(supported_operations[operation_idx].clone());
+                    position = previous_idx;
+                }
+                operations.reverse();
+
+                let mut solution = self.solution(operations);
+                solution.result = *value;
+                (*value, solution)
+            })
+            .collect()
+    }
+
+    /// The closest values below and above the result that the operands can
+    /// produce when evaluated left to right
+    pub fn nearest_misses(
+        &self,
+        supported_operations: &[Operation],
+    ) -> (Option<Solution>, Option<Solution>) {
+        let reachable = self.reachable_values(supported_operations);
+        (
+            reachable
+                .range(..self.result)
+                .next_back()
+                .map(|(_, solution)| solution.clone()),
+            reachable
+                .range((Excluded(self.result), Unbounded))
+                .next()
+                .map(|(_, solution)| solution.clone()),
+        )
+    }
+
+    fn solution(&self, operations: Vec<Operation>) -> Solution {
+        Solution {
+            result: self.result,
+            operands: self.operands.clone(),
+            operations,
+            grouping: None,
+        }
+    }
+
+    fn is_solved_by(&self, operation_sequence: &[Operation]) -> bool {
+        let mut operands = self.operands.iter();
+        let Some(mut acc) = operands.next().copied() else {
+            return false;
+        };
+
+        for (operation, operand) in operation_sequence.iter().zip(operands) {
+            match operation.apply(acc, *operand) {
+                Some(value) => acc = value,
+                None => return false,
+            }
+        }
+
+        self.result == acc
+    }
 }
 
-/// A struct representing a lazy permutation generator
-pub struct Permutations<T> {
-    symbols: Vec<T>,
-    length: usize,
-    current: Vec<usize>,
-    finished: bool,
+/// Counts the ways to reach the equation result from `acc`, applying
+/// operations to `operands[idx..]`. Counts are memoized by `(idx, acc)` since
+/// different prefixes often reach the same accumulated value.
+fn count_from(
+    equation: &CalibrationEquation,
+    supported_operations: &[Operation],
+    acc: Number,
+    idx: usize,
+    memo: &mut HashMap<(usize, Number), u128>,
+) -> u128 {
+    let Some(operand) = equation.operands.get(idx) else {
+        return u128::from(acc == equation.result);
+    };
+    if let Some(count) = memo.get(&(idx, acc)) {
+        return *count;
+    }
+
+    let count = supported_operations
+        .iter()
+        .filter_map(|operation| operation.apply(acc, *operand))
+        .map(|acc| count_from(equation, supported_operations, acc, idx + 1, memo))
+        .fold(0u128, u128::saturating_add);
+    memo.insert((idx, acc), count);
+    count
 }
 
-impl<T: Clone> Permutations<T> {
-    /// Create a new `Permutations` iterator
-    pub fn new(symbols: &[T], length: usize) -> Self {
-        Self {
-            symbols: Vec::from(symbols),
-            length,
-            current: vec![0; length],
-            finished: length == 0,
-        }
+/// Iterator over every solution of an equation, see
+/// [`CalibrationEquation::solutions`]
+pub struct Solutions<'a> {
+    equation: &'a CalibrationEquation,
+    supported_operations: &'a [Operation],
+    memo: HashMap<(usize, Number), u128>,
+    // One frame per operand walked so far, holding the value accumulated up
+    // to that operand
+    stack: Vec<Frame>,
+    // Index of the operation chosen between each pair of frames
+    path: Vec<usize>,
+}
+
+struct Frame {
+    acc: Number,
+    next_operation: usize,
+}
+
+impl Solutions<'_> {
+    fn count_from(&mut self, acc: Number, idx: usize) -> u128 {
+        count_from(
+            self.equation,
+            self.supported_operations,
+            acc,
+            idx,
+            &mut self.memo,
+        )
     }
 }
 
-impl<T: Clone> Iterator for Permutations<T> {
-    type Item = Vec<T>;
+impl Iterator for Solutions<'_> {
+    type Item = Solution;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.finished {
-            return None;
-        }
+        let last = self.equation.operands.len().checked_sub(1)?;
+        while let Some(frame) = self.stack.last_mut() {
+            let depth = self.path.len();
+            if depth == last {
+                // Only frames that can reach the result are ever pushed
+                let solution = self.equation.solution(
+                    self.path
+                        .iter()
+                        .map(|idx| self.supported_operations[*idx].clone())
+                        .collect(),
+                );
+                self.stack.pop();
+                self.path.pop();
+                return Some(solution);
+            }
 
-        // Generate the current permutation based on the indices in `self.current`
-        let permutation = self
-            .current
-            .iter()
-            .map(|&i| self.symbols[i].clone())
-            .collect();
-
-        // Update the indices to generate the next permutation
-        for i in (0..self.length).rev() {
-            if self.current[i] + 1 < self.symbols.len() {
-                self.current[i] += 1;
-                return Some(permutation);
-            } else {
-                self.current[i] = 0;
+            if frame.next_operation == self.supported_operations.len() {
+                self.stack.pop();
+                self.path.pop();
+                continue;
+            }
+
+            let operation_idx = frame.next_operation;
+            let acc = frame.acc;
+            frame.next_operation += 1;
+
+            let operand = self.equation.operands[depth + 1];
+            if let Some(next_acc) = self.supported_operations[operation_idx].apply(acc, operand) {
+                if self.count_from(next_acc, depth + 2) > 0 {
+                    self.path.push(operation_idx);
+                    self.stack.push(Frame {
+                        acc: next_acc,
+                        next_operation: 0,
+                    });
+                }
             }
         }
 
-        // If we reach here, all permutations are generated
-        self.finished = true;
-        Some(permutation)
+        None
+    }
+}
+
+impl<T: AsRef<str>> From<T> for CalibrationEquation {
+    fn from(value: T) -> Self {
+        let mut chunks = value.as_ref().split(":");
+        let result = chunks.next().expect("No result chunk found");
+        let operands = chunks.next().expect("No result chunk found").trim();
+
+        Self {
+            result: parse_number(result, "result"),
+            operands: operands
+                .split(" ")
+                .map(|v| parse_number(v, "operand"))
+                .collect::<Vec<Number>>(),
+        }
     }
 }
+
+fn parse_number(value: &str, what: &str) -> Number {
+    value.parse().unwrap_or_else(|err| {
+        if value.chars().all(|c| c.is_ascii_digit()) {
+            #[cfg(not(feature = "wide"))]
+            panic!(
+                "{} {} does not fit in {} bits, build with the `wide` feature",
+                what,
+                value,
+                Number::BITS
+            );
+            #[cfg(feature = "wide")]
+            panic!("{} {} does not fit in {} bits", what, value, Number::BITS);
+        } else {
+            panic!("Invalid {} {}: {}", what, value, err)
+        }
+    })
+}
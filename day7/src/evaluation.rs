@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::operator::{Number, Operation};
+
+/// How the operators of a calibration equation are evaluated
+#[derive(Debug, Clone, Default)]
+pub enum EvaluationMode {
+    /// Strictly left to right, ignoring any precedence
+    #[default]
+    LeftToRight,
+    /// Higher precedence operators bind tighter, operators of the same
+    /// precedence are evaluated left to right
+    Precedence(PrecedenceTable),
+    /// Operands can be grouped in any way with parentheses
+    AnyGrouping,
+}
+
+/// Operator precedences, overriding the ones the operators declare
+#[derive(Debug, Clone, Default)]
+pub struct PrecedenceTable {
+    overrides: HashMap<String, u8>,
+}
+
+impl PrecedenceTable {
+    pub fn with_override(mut self, name: &str, precedence: u8) -> Self {
+        self.overrides.insert(name.to_string(), precedence);
+        self
+    }
+
+    pub fn precedence(&self, operation: &Operation) -> u8 {
+        self.overrides
+            .get(operation.name())
+            .copied()
+            .unwrap_or_else(|| operation.precedence())
+    }
+
+    /// Evaluates `operands` combined by `operations`, honouring precedences
+    pub fn evaluate(&self, operands: &[Number], operations: &[Operation]) -> Option<Number> {
+        let mut values = vec![*operands.first()?];
+        let mut pending: Vec<&Operation> = vec![];
+
+        for (operation, operand) in operations.iter().zip(operands.iter().skip(1)) {
+            while pending
+                .last()
+                .is_some_and(|top| self.precedence(top) >= self.precedence(operation))
+            {
+                reduce(&mut values, &mut pending)?;
+            }
+            pending.push(operation);
+            values.push(*operand);
+        }
+
+        while !pending.is_empty() {
+            reduce(&mut values, &mut pending)?;
+        }
+
+        values.pop()
+    }
+}
+
+fn reduce(values: &mut Vec<Number>, pending: &mut Vec<&Operation>) -> Option<()> {
+    let operation = pending.pop()?;
+    let m = values.pop()?;
+    let n = values.pop()?;
+    values.push(operation.apply(n, m)?);
+    Some(())
+}
+
+/// A fully parenthesized expression over some operands
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Operand(Number),
+    Apply(Operation, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// The operations of the expression, from left to right
+    pub fn operations(&self) -> Vec<Operation> {
+        match self {
+            Expression::Operand(_) => vec![],
+            Expression::Apply(operation, left, right) => {
+                let mut operations = left.operations();
+                operations.push(operation.clone());
+                operations.extend(right.operations());
+                operations
+            }
+        }
+    }
+
+    /// Looks for a way to group `operands` with parentheses and combine them
+    /// with `operations` so that they produce `target`.
+    ///
+    /// For each range of operands this computes every value it can produce,
+    /// remembering how it was produced the first time.
+    pub fn find_grouping(
+        operands: &[Number],
+        operations: &[Operation],
+        target: Number,
+    ) -> Option<Expression> {
+        let n = operands.len();
+        if n == 0 {
+            return None;
+        }
+
+        // reachable[i][j] maps the values produced by operands[i..=j] to the
+        // split point, operation and sub values that produced them
+        let mut reachable: Vec<Vec<HashMap<Number, Option<Split>>>> =
+            vec![vec![HashMap::new(); n]; n];
+        for (i, operand) in operands.iter().enumerate() {
+            reachable[i][i].insert(*operand, None);
+        }
+
+        for length in 2..=n {
+            for i in 0..=(n - length) {
+                let j = i + length - 1;
+                let mut values: HashMap<Number, Option<Split>> = HashMap::new();
+                for k in i..j {
+                    for left in reachable[i][k].keys() {
+                        for right in reachable[k + 1][j].keys() {
+                            for (operation_idx, operation) in operations.iter().enumerate() {
+                                if let Some(value) = operation.apply(*left, *right) {
+                                    values.entry(value).or_insert(Some(Split {
+                                        at: k,
+                                        operation_idx,
+                                        left: *left,
+                                        right: *right,
+                                    }));
+                                }
+                            }
+                        }
+                    }
+                }
+                reachable[i][j] = values;
+            }
+        }
+
+        reachable[0][n - 1]
+            .contains_key(&target)
+            .then(|| Self::rebuild(&reachable, operations, 0, n - 1, target))
+    }
+
+    fn rebuild(
+        reachable: &[Vec<HashMap<Number, Option<Split>>>],
+        operations: &[Operation],
+        i: usize,
+        j: usize,
+        value: Number,
+    ) -> Expression {
+        match &reachable[i][j][&value] {
+            None => Expression::Operand(value),
+            Some(split) => Expression::Apply(
+                operations[split.operation_idx].clone(),
+                Box::new(Self::rebuild(
+                    reachable, operations, i, split.at, split.left,
+                )),
+                Box::new(Self::rebuild(
+                    reachable,
+                    operations,
+                    split.at + 1,
+                    j,
+                    split.right,
+                )),
+            ),
+        }
+    }
+
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>, nested: bool) -> std::fmt::Result {
+        match self {
+            Expression::Operand(value) => write!(f, "{}", value),
+            Expression::Apply(operation, left, right) => {
+                if nested {
+                    write!(f, "(")?;
+                }
+                left.fmt_nested(f, true)?;
+                write!(f, " {} ", operation)?;
+                right.fmt_nested(f, true)?;
+                if nested {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_nested(f, false)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Split {
+    at: usize,
+    operation_idx: usize,
+    left: Number,
+    right: Number,
+}
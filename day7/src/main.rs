@@ -1,50 +1,205 @@
 mod calculator;
+mod evaluation;
+mod operator;
 
-use std::env;
+use std::{collections::BTreeMap, env, process, sync::Arc};
 
-use calculator::{CalibrationEquation, Operation};
+use calculator::{CalibrationEquation, Solution};
 use common::read_input;
+use evaluation::{EvaluationMode, PrecedenceTable};
+use operator::{Concat, Mul, Operation, OperatorRegistry, Sum};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let raw_input: String = read_input(args.get(1).unwrap_or(&String::from("day7/input.txt")));
-    let equations: Vec<CalibrationEquation> = raw_input
-        .lines()
-        .map(CalibrationEquation::from)
-        .collect();
-    let result: u64 = equations
-        .par_iter()
-        .map(|e| {
-            // println!("Trying to solve {:?}", e);
-            if e.try_solve(&[Operation::Sum, Operation::Mul]).is_some() {
-                e.result()
-            } else {
-                0
+    let mode = match flag_value(&args, "--eval") {
+        None | Some("left-to-right") => EvaluationMode::LeftToRight,
+        Some("precedence") => {
+            let mut table = PrecedenceTable::default();
+            if let Some(precedence) = flag_value(&args, "--concat-precedence") {
+                table = table.with_override(
+                    "concat",
+                    precedence.parse().expect("Invalid concat precedence"),
+                );
             }
-        })
-        .sum();
-    println!("Sum of solvable equation results: {}", result);
+            EvaluationMode::Precedence(table)
+        }
+        Some("grouping") => EvaluationMode::AnyGrouping,
+        Some(other) => {
+            eprintln!(
+                "Unknown evaluation mode '{}', expected one of: left-to-right, precedence, grouping",
+                other
+            );
+            process::exit(1);
+        }
+    };
+    // These searches only evaluate left to right
+    if !matches!(mode, EvaluationMode::LeftToRight) {
+        let left_to_right_flags = ["--brute-force", "--all-solutions", "--near-miss", "--count"];
+        if let Some(flag) = left_to_right_flags
+            .iter()
+            .find(|flag| args.iter().any(|arg| arg == *flag))
+        {
+            eprintln!("{} only supports left-to-right evaluation", flag);
+            process::exit(1);
+        }
+    }
+    let brute_force = args.iter().any(|arg| arg == "--brute-force");
+    let solve = |e: &CalibrationEquation, operations: &[Operation]| {
+        if brute_force {
+            e.try_solve_brute_force(operations)
+        } else {
+            e.try_solve_with(operations, &mode)
+        }
+    };
+    let print_witnesses = args.iter().any(|arg| arg == "--witness");
 
-    let equations: Vec<CalibrationEquation> = raw_input
-        .lines()
-        .map(CalibrationEquation::from)
-        .collect();
-    let result: u64 = equations
-        .par_iter()
-        .map(|e| {
-            // println!("Trying to solve {:?}", e);
-            if e.try_solve(&[Operation::Sum, Operation::Mul, Operation::Concat])
-                .is_some()
-            {
-                e.result()
-            } else {
-                0
+    let equations: Vec<CalibrationEquation> =
+        raw_input.lines().map(CalibrationEquation::from).collect();
+
+    let runs: Vec<(Option<String>, Vec<Operation>)> = match flag_value(&args, "--ops") {
+        Some(names) => {
+            let operations = OperatorRegistry::with_builtins()
+                .resolve(names)
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                });
+            vec![(Some(format!("with {} operators", names)), operations)]
+        }
+        None => vec![
+            (None, vec![Arc::new(Sum), Arc::new(Mul)]),
+            (
+                Some(String::from("with || operator supported")),
+                vec![Arc::new(Sum), Arc::new(Mul), Arc::new(Concat)],
+            ),
+        ],
+    };
+
+    let enumerates = brute_force || matches!(mode, EvaluationMode::Precedence(_));
+    for (label, operations) in runs.iter() {
+        if enumerates {
+            for (line, equation) in equations.iter().enumerate() {
+                if !equation.is_enumerable(operations) {
+                    eprintln!(
+                        "Skipping line {}, it has too many sequences of operations to try",
+                        line + 1
+                    );
+                }
             }
-        })
-        .sum();
+        }
+
+        let Some(result) = sum_solvable(&equations, operations, &solve, print_witnesses) else {
+            eprintln!("The sum of solvable equation results does not fit in 128 bits");
+            process::exit(1);
+        };
+        match label {
+            Some(label) => println!("Sum of solvable equation results ({}): {}", label, result),
+            None => println!("Sum of solvable equation results: {}", result),
+        }
+
+        if args.iter().any(|arg| arg == "--all-solutions") {
+            for equation in equations.iter() {
+                for solution in equation.solutions(operations) {
+                    println!("{}", solution);
+                }
+            }
+        }
+
+        if args.iter().any(|arg| arg == "--near-miss") {
+            print_near_misses(&equations, operations, &solve);
+        }
+
+        if args.iter().any(|arg| arg == "--count") {
+            print_multiplicities(&equations, operations);
+        }
+    }
+}
+
+/// Prints the closest values unsolvable equations can reach
+fn print_near_misses<F>(equations: &[CalibrationEquation], operations: &[Operation], solve: &F)
+where
+    F: Fn(&CalibrationEquation, &[Operation]) -> Option<Solution> + Sync,
+{
+    for (line, equation) in equations.iter().enumerate() {
+        if solve(equation, operations).is_some() {
+            continue;
+        }
+
+        let (below, above) = equation.nearest_misses(operations);
+        let describe = |miss: Option<Solution>| match miss {
+            Some(solution) => solution.to_string(),
+            None => String::from("none"),
+        };
+        println!(
+            "Line {} ({}) is unsolvable, closest below: {}, closest above: {}",
+            line + 1,
+            equation.result(),
+            describe(below),
+            describe(above)
+        );
+    }
+}
+
+/// Prints how many equations have each number of solutions
+fn print_multiplicities(equations: &[CalibrationEquation], operations: &[Operation]) {
+    let counts: Vec<u128> = equations
+        .par_iter()
+        .map(|e| e.count_solutions(operations))
+        .collect();
+
+    let mut multiplicities: BTreeMap<u128, usize> = BTreeMap::new();
+    for count in counts.iter() {
+        *multiplicities.entry(*count).or_default() += 1;
+    }
+
+    println!("Solutions per equation:");
+    for (count, n_equations) in multiplicities {
+        println!("  {} solutions: {} equations", count, n_equations);
+    }
     println!(
-        "Sum of solvable equation results (with || operator supported): {}",
-        result
+        "Ambiguous equations: {}",
+        counts.iter().filter(|count| **count > 1).count()
     );
 }
+
+/// Sums the results of the solvable equations, or returns `None` if the sum
+/// overflows
+// Results are already 128 bits wide with the `wide` feature
+#[cfg_attr(feature = "wide", allow(clippy::useless_conversion))]
+fn sum_solvable<F>(
+    equations: &[CalibrationEquation],
+    supported_operations: &[Operation],
+    solve: &F,
+    print_witnesses: bool,
+) -> Option<u128>
+where
+    F: Fn(&CalibrationEquation, &[Operation]) -> Option<Solution> + Sync,
+{
+    let solutions: Vec<Option<Solution>> = equations
+        .par_iter()
+        .map(|e| solve(e, supported_operations))
+        .collect();
+
+    // Sum in 128 bits, so that many large results only overflow with the
+    // `wide` feature
+    let mut result: u128 = 0;
+    for (equation, solution) in equations.iter().zip(solutions) {
+        if let Some(solution) = solution {
+            if print_witnesses {
+                println!("{}", solution);
+            }
+            result = result.checked_add(u128::from(equation.result()))?;
+        }
+    }
+
+    Some(result)
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .map(String::as_str)
+}
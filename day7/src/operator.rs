@@ -0,0 +1,341 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+/// The numbers calibration equations work with. Build with the `wide`
+/// feature to handle values that do not fit in 64 bits.
+#[cfg(not(feature = "wide"))]
+pub type Number = u64;
+#[cfg(feature = "wide")]
+pub type Number = u128;
+
+/// A shared handle to an operator, as stored in registries and solutions
+pub type Operation = Arc<dyn Operator>;
+
+/// The left operands that produce a given result with a given right operand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preimage {
+    /// No left operand produces the result
+    None,
+    /// Exactly one left operand produces the result
+    One(Number),
+    /// Any left operand produces the result
+    Any,
+}
+
+/// A binary operator that can appear between the operands of a calibration
+/// equation
+pub trait Operator: Debug + Send + Sync {
+    /// The name used to pick the operator, e.g. on the command line
+    fn name(&self) -> &str;
+
+    fn symbol(&self) -> &str;
+
+    /// How tightly the operator binds when evaluating with precedences,
+    /// higher values binding tighter
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    /// Combines the value accumulated so far with the next operand, or
+    /// returns `None` if the operation is undefined for them or the result
+    /// does not fit in a `Number`
+    fn apply(&self, n: Number, m: Number) -> Option<Number>;
+
+    /// Whether the operator never makes the accumulated value smaller, for
+    /// any right operand but zero, and never yields a smaller result from a
+    /// larger accumulated value. Searches use this to prune values that
+    /// already overshoot the result.
+    fn is_monotone(&self) -> bool {
+        false
+    }
+
+    /// Finds the left operands `n` such that `apply(n, m) == result`.
+    /// Operators that can be undone must return `Some` for any input, the
+    /// others keep the default and are only ever solved forwards.
+    fn inverse(&self, _result: Number, _m: Number) -> Option<Preimage> {
+        None
+    }
+}
+
+impl Display for dyn Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+#[derive(Debug)]
+pub struct Sum;
+
+impl Operator for Sum {
+    fn name(&self) -> &str {
+        "sum"
+    }
+
+    fn symbol(&self) -> &str {
+        "+"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_add(m)
+    }
+
+    fn is_monotone(&self) -> bool {
+        true
+    }
+
+    fn inverse(&self, result: Number, m: Number) -> Option<Preimage> {
+        Some(result.checked_sub(m).map_or(Preimage::None, Preimage::One))
+    }
+}
+
+#[derive(Debug)]
+pub struct Mul;
+
+impl Operator for Mul {
+    fn name(&self) -> &str {
+        "mul"
+    }
+
+    fn symbol(&self) -> &str {
+        "*"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_mul(m)
+    }
+
+    fn is_monotone(&self) -> bool {
+        true
+    }
+
+    fn inverse(&self, result: Number, m: Number) -> Option<Preimage> {
+        Some(match (result, m) {
+            // Multiplying by zero yields zero whatever came before
+            (0, 0) => Preimage::Any,
+            (_, 0) => Preimage::None,
+            _ if result.is_multiple_of(m) => Preimage::One(result / m),
+            _ => Preimage::None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Concat;
+
+impl Operator for Concat {
+    fn name(&self) -> &str {
+        "concat"
+    }
+
+    fn symbol(&self) -> &str {
+        "||"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_mul(concat_shift(m)?)?.checked_add(m)
+    }
+
+    fn is_monotone(&self) -> bool {
+        true
+    }
+
+    fn inverse(&self, result: Number, m: Number) -> Option<Preimage> {
+        // Nothing can be concatenated to the widest numbers, as `apply` has
+        // no room for their digits
+        let preimage =
+            concat_shift(m).and_then(|shift| (result % shift == m).then(|| result / shift));
+        Some(preimage.map_or(Preimage::None, Preimage::One))
+    }
+}
+
+/// The power of ten that makes room for the digits of `m` when concatenating
+fn concat_shift(m: Number) -> Option<Number> {
+    let digits = m.checked_ilog10().unwrap_or(0) + 1;
+    Number::from(10u8).checked_pow(digits)
+}
+
+#[derive(Debug)]
+pub struct Sub;
+
+impl Operator for Sub {
+    fn name(&self) -> &str {
+        "sub"
+    }
+
+    fn symbol(&self) -> &str {
+        "-"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_sub(m)
+    }
+
+    fn inverse(&self, result: Number, m: Number) -> Option<Preimage> {
+        Some(result.checked_add(m).map_or(Preimage::None, Preimage::One))
+    }
+}
+
+#[derive(Debug)]
+pub struct Div;
+
+impl Operator for Div {
+    fn name(&self) -> &str {
+        "div"
+    }
+
+    fn symbol(&self) -> &str {
+        "/"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_div(m)
+    }
+}
+
+#[derive(Debug)]
+pub struct Xor;
+
+impl Operator for Xor {
+    fn name(&self) -> &str {
+        "xor"
+    }
+
+    fn symbol(&self) -> &str {
+        "^"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        Some(n ^ m)
+    }
+
+    fn inverse(&self, result: Number, m: Number) -> Option<Preimage> {
+        Some(Preimage::One(result ^ m))
+    }
+}
+
+#[derive(Debug)]
+pub struct Max;
+
+impl Operator for Max {
+    fn name(&self) -> &str {
+        "max"
+    }
+
+    fn symbol(&self) -> &str {
+        "max"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        Some(n.max(m))
+    }
+
+    fn is_monotone(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct Min;
+
+impl Operator for Min {
+    fn name(&self) -> &str {
+        "min"
+    }
+
+    fn symbol(&self) -> &str {
+        "min"
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        Some(n.min(m))
+    }
+}
+
+#[derive(Debug)]
+pub struct Mod;
+
+impl Operator for Mod {
+    fn name(&self) -> &str {
+        "mod"
+    }
+
+    fn symbol(&self) -> &str {
+        "%"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, n: Number, m: Number) -> Option<Number> {
+        n.checked_rem(m)
+    }
+}
+
+/// A set of operators that can be looked up by name
+#[derive(Debug, Clone, Default)]
+pub struct OperatorRegistry {
+    operators: Vec<Operation>,
+}
+
+impl OperatorRegistry {
+    /// A registry with all the operators this crate knows about
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register(Arc::new(Sum));
+        registry.register(Arc::new(Mul));
+        registry.register(Arc::new(Concat));
+        registry.register(Arc::new(Sub));
+        registry.register(Arc::new(Div));
+        registry.register(Arc::new(Xor));
+        registry.register(Arc::new(Max));
+        registry.register(Arc::new(Min));
+        registry.register(Arc::new(Mod));
+        registry
+    }
+
+    /// Adds an operator, replacing any other registered under the same name
+    pub fn register(&mut self, operator: Operation) {
+        self.operators
+            .retain(|registered| registered.name() != operator.name());
+        self.operators.push(operator);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Operation> {
+        self.operators
+            .iter()
+            .find(|operator| operator.name() == name)
+            .cloned()
+    }
+
+    /// Looks up a comma separated list of operator names, e.g. `sum,mul`
+    pub fn resolve(&self, names: &str) -> Result<Vec<Operation>, String> {
+        names
+            .split(',')
+            .map(str::trim)
+            .map(|name| {
+                self.get(name).ok_or_else(|| {
+                    format!(
+                        "Unknown operator '{}', expected one of: {}",
+                        name,
+                        self.operators
+                            .iter()
+                            .map(|operator| operator.name())
+                            .collect::<Vec<&str>>()
+                            .join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+}